@@ -0,0 +1,59 @@
+//! Compares animating and culling the instances on the CPU with the compute path, at a few
+//! instance counts. Run with `cargo bench --bench instances`.
+
+use std::time::{Duration, Instant};
+
+use advanced_wgpu::State;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+const WARMUP_FRAMES: u32 = 5;
+const FRAMES: u32 = 50;
+
+/// Average time to update, render and read back one frame.
+fn frame_time(state: &mut State) -> Duration {
+    for _ in 0..WARMUP_FRAMES {
+        state.update();
+        state.capture_frame().unwrap();
+    }
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        state.update();
+        // Reading the frame back waits for the GPU to finish it
+        state.capture_frame().unwrap();
+    }
+    start.elapsed() / FRAMES
+}
+
+fn main() {
+    env_logger::init();
+    let mut state = match pollster::block_on(State::new_headless(WIDTH, HEIGHT)) {
+        Ok(state) => state,
+        Err(err) => {
+            eprintln!("Skipping benchmark: {:#}", err);
+            return;
+        }
+    };
+
+    let mut results = Vec::new();
+    for per_row in [10, 100, 300] {
+        state.set_instance_grid(per_row);
+        state.set_gpu_instances(false);
+        let cpu = frame_time(&mut state);
+        let gpu = state
+            .set_gpu_instances(true)
+            .then(|| frame_time(&mut state));
+        results.push((per_row * per_row, cpu, gpu));
+    }
+
+    println!("{:>10} {:>12} {:>12}", "instances", "cpu", "gpu");
+    for (instances, cpu, gpu) in results {
+        let gpu = gpu.map_or("unsupported".to_string(), |gpu| format!("{:.2?}", gpu));
+        println!(
+            "{:>10} {:>12} {:>12}",
+            instances,
+            format!("{:.2?}", cpu),
+            gpu
+        );
+    }
+}
@@ -0,0 +1,40 @@
+struct Bloom {
+    threshold: f32,
+    // Width of the soft transition below the threshold, as a fraction of it
+    knee: f32,
+    intensity: f32,
+    // Set for the first pass, which reads the scene and keeps only its highlights
+    apply_threshold: u32,
+}
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+@group(0) @binding(2)
+var<uniform> bloom: Bloom;
+
+fn soft_threshold(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = bloom.threshold * bloom.knee;
+    var soft = clamp(brightness - bloom.threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 0.00001);
+    let contribution = max(soft, brightness - bloom.threshold) / max(brightness, 0.00001);
+    return color * contribution;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Four bilinear taps a texel apart average a 4x4 block of the source
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    var color = textureSample(t_source, s_source, in.uv + texel * vec2<f32>(-1.0, -1.0)).rgb;
+    color = color + textureSample(t_source, s_source, in.uv + texel * vec2<f32>(1.0, -1.0)).rgb;
+    color = color + textureSample(t_source, s_source, in.uv + texel * vec2<f32>(-1.0, 1.0)).rgb;
+    color = color + textureSample(t_source, s_source, in.uv + texel * vec2<f32>(1.0, 1.0)).rgb;
+    color = max(color * 0.25, vec3<f32>(0.0));
+
+    if (bloom.apply_threshold != 0u) {
+        color = soft_threshold(color);
+    }
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,30 @@
+struct Bloom {
+    threshold: f32,
+    knee: f32,
+    // Scale of what's added to the target, which blends additively
+    intensity: f32,
+    apply_threshold: u32,
+}
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+@group(0) @binding(2)
+var<uniform> bloom: Bloom;
+
+fn tap(uv: vec2<f32>, offset: vec2<f32>) -> vec3<f32> {
+    return textureSample(t_source, s_source, uv + offset).rgb;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // 3x3 tent filter over the smaller source
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    var color = tap(in.uv, vec2<f32>(0.0)) * 4.0;
+    color = color + (tap(in.uv, vec2<f32>(-texel.x, 0.0)) + tap(in.uv, vec2<f32>(texel.x, 0.0))) * 2.0;
+    color = color + (tap(in.uv, vec2<f32>(0.0, -texel.y)) + tap(in.uv, vec2<f32>(0.0, texel.y))) * 2.0;
+    color = color + tap(in.uv, -texel) + tap(in.uv, texel);
+    color = color + tap(in.uv, vec2<f32>(-texel.x, texel.y)) + tap(in.uv, vec2<f32>(texel.x, -texel.y));
+    return vec4<f32>(color / 16.0 * bloom.intensity, 0.0);
+}
@@ -0,0 +1,306 @@
+use cgmath::prelude::*;
+use winit::event::*;
+
+use crate::Camera;
+
+/// How a [`Camera`] projects the scene.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// Uses the camera's vertical field of view.
+    Perspective,
+    /// Shows `half_height` units above and below the view direction; the width follows the
+    /// aspect ratio so the scene isn't stretched.
+    Orthographic { half_height: f32 },
+}
+
+/// Pitch is kept just short of straight up or down, where the view's up vector degenerates.
+const MAX_PITCH: cgmath::Deg<f32> = cgmath::Deg(89.0);
+const MIN_SPEED: f32 = 0.1;
+const MAX_SPEED: f32 = 100.0;
+/// Speed multiplier for one line (or 40 pixels) of scrolling.
+const SCROLL_SPEED_FACTOR: f32 = 1.1;
+
+/// First-person camera: the mouse turns the view, W/A/S/D (or the arrow keys) move along it,
+/// E and Q move straight up and down, and the scroll wheel changes the speed.
+pub(crate) struct FlyCameraController {
+    yaw: cgmath::Rad<f32>,
+    pitch: cgmath::Rad<f32>,
+    /// Movement speed in units per second.
+    speed: f32,
+    /// Radians turned per pixel of mouse motion.
+    sensitivity: f32,
+    forward: f32,
+    backward: f32,
+    left: f32,
+    right: f32,
+    up: f32,
+    down: f32,
+    /// Mouse motion since the last update.
+    rotate_horizontal: f32,
+    rotate_vertical: f32,
+}
+impl FlyCameraController {
+    pub fn new(speed: f32, sensitivity: f32) -> Self {
+        Self {
+            yaw: cgmath::Rad(0.0),
+            pitch: cgmath::Rad(0.0),
+            speed,
+            sensitivity,
+            forward: 0.0,
+            backward: 0.0,
+            left: 0.0,
+            right: 0.0,
+            up: 0.0,
+            down: 0.0,
+            rotate_horizontal: 0.0,
+            rotate_vertical: 0.0,
+        }
+    }
+    /// Takes over `camera`, continuing to look the way it currently faces.
+    pub fn look_along(&mut self, camera: &Camera) {
+        let forward = (camera.target - camera.eye).normalize();
+        self.yaw = cgmath::Rad(forward.z.atan2(forward.x));
+        self.pitch = cgmath::Rad(forward.y.clamp(-1.0, 1.0).asin());
+    }
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => {
+                let amount = if *state == ElementState::Pressed {
+                    1.0
+                } else {
+                    0.0
+                };
+                match keycode {
+                    VirtualKeyCode::W | VirtualKeyCode::Up => self.forward = amount,
+                    VirtualKeyCode::S | VirtualKeyCode::Down => self.backward = amount,
+                    VirtualKeyCode::A | VirtualKeyCode::Left => self.left = amount,
+                    VirtualKeyCode::D | VirtualKeyCode::Right => self.right = amount,
+                    VirtualKeyCode::E => self.up = amount,
+                    VirtualKeyCode::Q => self.down = amount,
+                    _ => return false,
+                }
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+                };
+                self.speed =
+                    (self.speed * SCROLL_SPEED_FACTOR.powf(lines)).clamp(MIN_SPEED, MAX_SPEED);
+                true
+            }
+            _ => false,
+        }
+    }
+    /// Accumulates raw mouse motion, from `DeviceEvent::MouseMotion`.
+    pub fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        self.rotate_horizontal += dx as f32;
+        self.rotate_vertical += dy as f32;
+    }
+    /// Applies the input since the last update, scaled by the frame time `dt` in seconds.
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        self.yaw += cgmath::Rad(self.rotate_horizontal * self.sensitivity);
+        self.pitch -= cgmath::Rad(self.rotate_vertical * self.sensitivity);
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
+        let max_pitch = cgmath::Rad::from(MAX_PITCH);
+        if self.pitch > max_pitch {
+            self.pitch = max_pitch;
+        } else if self.pitch < -max_pitch {
+            self.pitch = -max_pitch;
+        }
+
+        let (yaw_sin, yaw_cos) = self.yaw.0.sin_cos();
+        let (pitch_sin, pitch_cos) = self.pitch.0.sin_cos();
+        let forward = cgmath::Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin);
+        let right = cgmath::Vector3::new(-yaw_sin, 0.0, yaw_cos);
+
+        let distance = self.speed * dt;
+        camera.eye += forward * (self.forward - self.backward) * distance;
+        camera.eye += right * (self.right - self.left) * distance;
+        camera.eye.y += (self.up - self.down) * distance;
+        camera.target = camera.eye + forward;
+        camera.up = cgmath::Vector3::unit_y();
+    }
+}
+
+const MIN_DISTANCE: f32 = 0.2;
+const MAX_DISTANCE: f32 = 50.0;
+/// Distance multiplier for one line (or 40 pixels) of scrolling.
+const ZOOM_FACTOR: f32 = 0.9;
+/// Target movement per pixel dragged, relative to the distance from the target.
+const PAN_SENSITIVITY: f32 = 0.002;
+
+/// Orbits the camera around its target: left-drag rotates, right- or middle-drag pans the
+/// target and the scroll wheel zooms. A/D (or left/right) orbit and W/S (or up/down) zoom
+/// from the keyboard, and R toggles a slow turntable rotation.
+pub(crate) struct OrbitCameraController {
+    /// Radians turned per pixel dragged.
+    sensitivity: f32,
+    /// Keyboard orbit speed in radians per second.
+    orbit_speed: f32,
+    /// Keyboard zoom speed in units per second.
+    zoom_speed: f32,
+    /// Turntable speed in radians per second.
+    auto_rotate_speed: f32,
+    auto_rotate: bool,
+    rotating: bool,
+    panning: bool,
+    cursor: Option<winit::dpi::PhysicalPosition<f64>>,
+    /// Input since the last update.
+    rotate_delta: cgmath::Vector2<f32>,
+    pan_delta: cgmath::Vector2<f32>,
+    scroll_lines: f32,
+    left: f32,
+    right: f32,
+    zoom_in: f32,
+    zoom_out: f32,
+}
+impl OrbitCameraController {
+    pub fn new(sensitivity: f32) -> Self {
+        Self {
+            sensitivity,
+            orbit_speed: 1.5,
+            zoom_speed: 4.0,
+            auto_rotate_speed: 0.5,
+            auto_rotate: false,
+            rotating: false,
+            panning: false,
+            cursor: None,
+            rotate_delta: cgmath::Vector2::zero(),
+            pan_delta: cgmath::Vector2::zero(),
+            scroll_lines: 0.0,
+            left: 0.0,
+            right: 0.0,
+            zoom_in: 0.0,
+            zoom_out: 0.0,
+        }
+    }
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => {
+                let pressed = *state == ElementState::Pressed;
+                let amount = if pressed { 1.0 } else { 0.0 };
+                match keycode {
+                    VirtualKeyCode::W | VirtualKeyCode::Up => self.zoom_in = amount,
+                    VirtualKeyCode::S | VirtualKeyCode::Down => self.zoom_out = amount,
+                    VirtualKeyCode::A | VirtualKeyCode::Left => self.left = amount,
+                    VirtualKeyCode::D | VirtualKeyCode::Right => self.right = amount,
+                    VirtualKeyCode::R if pressed => self.auto_rotate = !self.auto_rotate,
+                    VirtualKeyCode::R => {}
+                    _ => return false,
+                }
+                true
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let pressed = *state == ElementState::Pressed;
+                match button {
+                    MouseButton::Left => self.rotating = pressed,
+                    MouseButton::Right | MouseButton::Middle => self.panning = pressed,
+                    MouseButton::Other(_) => return false,
+                }
+                true
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some(last) = self.cursor {
+                    let delta = cgmath::Vector2::new(
+                        (position.x - last.x) as f32,
+                        (position.y - last.y) as f32,
+                    );
+                    if self.rotating {
+                        self.rotate_delta += delta;
+                    }
+                    if self.panning {
+                        self.pan_delta += delta;
+                    }
+                }
+                self.cursor = Some(*position);
+                self.rotating || self.panning
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll_lines += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+    fn is_idle(&self) -> bool {
+        !self.auto_rotate
+            && self.rotate_delta.is_zero()
+            && self.pan_delta.is_zero()
+            && self.scroll_lines == 0.0
+            && self.left == self.right
+            && self.zoom_in == self.zoom_out
+    }
+    /// Applies the input since the last update, scaled by the frame time `dt` in seconds.
+    /// The orbit is derived from the camera each time, so it picks up cameras set elsewhere.
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        if self.is_idle() {
+            return;
+        }
+
+        let mut offset = camera.eye - camera.target;
+        // An eye on its target has no direction to orbit in, so back away along +Z
+        if offset.magnitude2() < MIN_DISTANCE * MIN_DISTANCE * 1e-6 {
+            offset = cgmath::Vector3::unit_z() * MIN_DISTANCE;
+        }
+        let mut distance = offset.magnitude();
+        let mut yaw = offset.z.atan2(offset.x);
+        let mut pitch = (offset.y / distance).clamp(-1.0, 1.0).asin();
+
+        yaw += self.rotate_delta.x * self.sensitivity
+            + (self.right - self.left) * self.orbit_speed * dt;
+        if self.auto_rotate {
+            yaw += self.auto_rotate_speed * dt;
+        }
+        let max_pitch = cgmath::Rad::from(MAX_PITCH).0;
+        pitch = (pitch + self.rotate_delta.y * self.sensitivity).clamp(-max_pitch, max_pitch);
+
+        distance *= ZOOM_FACTOR.powf(self.scroll_lines);
+        distance += (self.zoom_out - self.zoom_in) * self.zoom_speed * dt;
+        distance = distance.clamp(MIN_DISTANCE, MAX_DISTANCE);
+
+        // Pan in the view plane, so the target follows the cursor
+        let forward = -offset.normalize();
+        let right = forward.cross(cgmath::Vector3::unit_y());
+        // Looking straight up or down, e.g. with a camera from a file: use the yaw's right
+        let right = if right.magnitude2() > 1e-6 {
+            right.normalize()
+        } else {
+            cgmath::Vector3::new(yaw.sin(), 0.0, -yaw.cos())
+        };
+        let up = right.cross(forward);
+        let pan = self.pan_delta * PAN_SENSITIVITY * distance;
+        camera.target += up * pan.y - right * pan.x;
+
+        let (yaw_sin, yaw_cos) = yaw.sin_cos();
+        let (pitch_sin, pitch_cos) = pitch.sin_cos();
+        camera.eye = camera.target
+            + cgmath::Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin) * distance;
+        camera.up = cgmath::Vector3::unit_y();
+
+        self.rotate_delta = cgmath::Vector2::zero();
+        self.pan_delta = cgmath::Vector2::zero();
+        self.scroll_lines = 0.0;
+    }
+}
@@ -1,4 +1,5 @@
 struct CameraUniform {
+    view_position: vec4<f32>,
     view_proj: mat4x4<f32>,
 }
 @group(1) @binding(0)
@@ -0,0 +1,35 @@
+struct ColorGrading {
+    // 0 leaves the colors unchanged, 1 applies the LUT fully
+    strength: f32,
+    // Set when the surface is sRGB, so values are encoded before the lookup and decoded after
+    srgb_surface: u32,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(0) @binding(2)
+var<uniform> grading: ColorGrading;
+@group(0) @binding(3)
+var t_lut: texture_3d<f32>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let input = textureSample(t_input, s_input, in.uv);
+    var color = clamp(input.rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+    if (grading.srgb_surface != 0u) {
+        color = srgb_encode(color);
+    }
+
+    // Look up between the texel centers, so 0 and 1 hit the first and last entries
+    let size = f32(textureDimensions(t_lut).x);
+    let coord = color * ((size - 1.0) / size) + 0.5 / size;
+    let graded = textureSample(t_lut, s_input, coord).rgb;
+    color = mix(color, graded, grading.strength);
+
+    if (grading.srgb_surface != 0u) {
+        color = srgb_decode(color);
+    }
+    return vec4<f32>(color, input.a);
+}
@@ -0,0 +1,243 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::*;
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+
+/// Usage text for the command-line flags parsed by [`Config::from_args`].
+pub const USAGE: &str = "\
+Usage: advanced_wgpu [OPTIONS] [FILE]
+
+Shows FILE (an OBJ model, a glTF scene or a .ron/.json scene file) instead of the pentagons.
+
+Options:
+    --config PATH           Read options from a TOML file; flags override it
+    --backend NAME          all, primary, vulkan, metal, dx12, dx11, gl or browser-webgpu
+    --power-preference P    low-power or high-performance
+    --fallback-adapter      Force the fallback (software) adapter
+    --present-mode MODE     fifo, mailbox or immediate
+    --surface-format FMT    bgra8unorm, bgra8unorm-srgb, rgba8unorm, rgba8unorm-srgb,
+                            rgba16float or rgb10a2unorm
+    --msaa SAMPLES          Multisample with 1 (off), 2, 4 or 8 samples per pixel
+    --size WIDTHxHEIGHT     Initial window size in physical pixels
+    --fullscreen            Start in borderless fullscreen
+    --help                  Print this text";
+
+/// How to set up the window, adapter and surface, from [`Config::from_args`] or a TOML file
+/// with the same names in snake case:
+///
+/// ```toml
+/// backend = "vulkan"
+/// power_preference = "high-performance"
+/// present_mode = "mailbox"
+/// width = 1280
+/// height = 720
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub backend: Backend,
+    pub power_preference: PowerPreference,
+    /// Only use the fallback (software) adapter.
+    pub force_fallback_adapter: bool,
+    /// Falls back to FIFO if the surface doesn't support it.
+    pub present_mode: PresentMode,
+    /// Falls back to the surface's preferred format if it doesn't support it.
+    pub surface_format: Option<SurfaceFormat>,
+    /// MSAA samples per pixel: 1 (off, the default), 2, 4 or 8. Changed to the nearest count
+    /// the adapter supports for the surface format.
+    pub msaa_samples: Option<u32>,
+    /// Initial window width in physical pixels, or the platform default.
+    pub width: Option<u32>,
+    /// Initial window height in physical pixels, or the platform default.
+    pub height: Option<u32>,
+    pub fullscreen: bool,
+    /// OBJ model, glTF scene or scene file to load at startup.
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    #[default]
+    All,
+    /// Vulkan, Metal, DX12 or WebGPU.
+    Primary,
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    Gl,
+    BrowserWebgpu,
+}
+impl From<Backend> for wgpu::Backends {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::All => wgpu::Backends::all(),
+            Backend::Primary => wgpu::Backends::PRIMARY,
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Dx11 => wgpu::Backends::DX11,
+            Backend::Gl => wgpu::Backends::GL,
+            Backend::BrowserWebgpu => wgpu::Backends::BROWSER_WEBGPU,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerPreference {
+    #[default]
+    LowPower,
+    HighPerformance,
+}
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(preference: PowerPreference) -> Self {
+        match preference {
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PresentMode {
+    /// Vsync, supported everywhere.
+    #[default]
+    Fifo,
+    /// Vsync without blocking, replacing the queued frame.
+    Mailbox,
+    /// No vsync, may tear.
+    Immediate,
+}
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(mode: PresentMode) -> Self {
+        match mode {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SurfaceFormat {
+    Bgra8unorm,
+    Bgra8unormSrgb,
+    Rgba8unorm,
+    Rgba8unormSrgb,
+    Rgba16float,
+    Rgb10a2unorm,
+}
+impl From<SurfaceFormat> for wgpu::TextureFormat {
+    fn from(format: SurfaceFormat) -> Self {
+        match format {
+            SurfaceFormat::Bgra8unorm => wgpu::TextureFormat::Bgra8Unorm,
+            SurfaceFormat::Bgra8unormSrgb => wgpu::TextureFormat::Bgra8UnormSrgb,
+            SurfaceFormat::Rgba8unorm => wgpu::TextureFormat::Rgba8Unorm,
+            SurfaceFormat::Rgba8unormSrgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            SurfaceFormat::Rgba16float => wgpu::TextureFormat::Rgba16Float,
+            SurfaceFormat::Rgb10a2unorm => wgpu::TextureFormat::Rgb10a2Unorm,
+        }
+    }
+}
+
+impl Config {
+    /// Reads a TOML config file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        let config: Self = toml::from_str(&source)
+            .with_context(|| format!("Invalid config {}", path.display()))?;
+        if let Some(samples) = config.msaa_samples {
+            check_msaa_samples(samples)
+                .map_err(|err| anyhow!("msaa_samples: {}", err))
+                .with_context(|| format!("Invalid config {}", path.display()))?;
+        }
+        Ok(config)
+    }
+    /// Parses command-line arguments (without the program name). Options from a `--config`
+    /// file come first, the other flags override them.
+    pub fn from_args<I>(args: I) -> Result<Self>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let args = args.into_iter().map(Into::into).collect::<Vec<String>>();
+        let mut config = match args.iter().position(|arg| arg == "--config") {
+            Some(i) => {
+                let path = args.get(i + 1).context("--config: missing path")?;
+                Self::load(path)?
+            }
+            None => Self::default(),
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .with_context(|| format!("{}: missing value", arg))
+            };
+            match arg.as_str() {
+                "--config" => {
+                    value()?;
+                }
+                "--backend" => config.backend = parse_name(&arg, &value()?)?,
+                "--power-preference" => config.power_preference = parse_name(&arg, &value()?)?,
+                "--fallback-adapter" => config.force_fallback_adapter = true,
+                "--present-mode" => config.present_mode = parse_name(&arg, &value()?)?,
+                "--surface-format" => config.surface_format = Some(parse_name(&arg, &value()?)?),
+                "--msaa" => {
+                    let samples = value()?;
+                    let samples = samples
+                        .parse()
+                        .ok()
+                        .with_context(|| format!("--msaa: expected a number, got {:?}", samples))?;
+                    check_msaa_samples(samples).map_err(|err| anyhow!("--msaa: {}", err))?;
+                    config.msaa_samples = Some(samples);
+                }
+                "--size" => {
+                    let size = value()?;
+                    let (width, height) = size
+                        .split_once('x')
+                        .and_then(|(width, height)| {
+                            Some((width.parse().ok()?, height.parse().ok()?))
+                        })
+                        .with_context(|| {
+                            format!("--size: expected WIDTHxHEIGHT, got {:?}", size)
+                        })?;
+                    config.width = Some(width);
+                    config.height = Some(height);
+                }
+                "--fullscreen" => config.fullscreen = true,
+                _ if arg.starts_with("--") => bail!("Unknown option {}", arg),
+                _ if config.file.is_none() => config.file = Some(arg.into()),
+                _ => bail!("Unexpected argument {:?}, only one file can be shown", arg),
+            }
+        }
+        Ok(config)
+    }
+    /// The window size to start with, if both dimensions are set.
+    pub fn window_size(&self) -> Option<winit::dpi::PhysicalSize<u32>> {
+        Some(winit::dpi::PhysicalSize::new(self.width?, self.height?))
+    }
+}
+
+fn check_msaa_samples(samples: u32) -> Result<()> {
+    ensure!(
+        matches!(samples, 1 | 2 | 4 | 8),
+        "expected 1, 2, 4 or 8 samples, got {}",
+        samples
+    );
+    Ok(())
+}
+
+/// Parses a flag value with the names the TOML file uses.
+fn parse_name<'de, T: Deserialize<'de>>(flag: &str, value: &'de str) -> Result<T> {
+    T::deserialize(value.into_deserializer())
+        .map_err(|err: serde::de::value::Error| anyhow!("{}: {}", flag, err))
+}
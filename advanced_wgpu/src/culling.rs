@@ -0,0 +1,90 @@
+use cgmath::prelude::*;
+
+use crate::{Instance, Vertex};
+
+/// A sphere enclosing a mesh, in the mesh's local space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: cgmath::Point3<f32>,
+    pub radius: f32,
+}
+impl BoundingSphere {
+    /// A sphere around the vertices' axis-aligned bounding box center. Not the tightest fit,
+    /// but cheap and never too small.
+    pub(crate) fn from_vertices(vertices: &[Vertex]) -> Self {
+        let Some(first) = vertices.first() else {
+            return Self {
+                center: cgmath::Point3::origin(),
+                radius: 0.0,
+            };
+        };
+        let (min, max) = vertices.iter().fold(
+            (first.position, first.position),
+            |(mut min, mut max), vertex| {
+                for i in 0..3 {
+                    min[i] = min[i].min(vertex.position[i]);
+                    max[i] = max[i].max(vertex.position[i]);
+                }
+                (min, max)
+            },
+        );
+        let center = cgmath::Point3::from(min).midpoint(cgmath::Point3::from(max));
+        let radius = vertices
+            .iter()
+            .map(|vertex| cgmath::Point3::from(vertex.position).distance(center))
+            .fold(0.0, f32::max);
+        Self { center, radius }
+    }
+    /// The sphere moved into world space by an instance. Non-uniform scale grows it by the
+    /// largest factor, so it still encloses the mesh.
+    pub(crate) fn transformed(&self, instance: &Instance) -> Self {
+        let scaled = self.center.to_vec().mul_element_wise(instance.scale);
+        let scale = instance.scale.map(f32::abs);
+        Self {
+            center: cgmath::Point3::from_vec(
+                instance.rotation.rotate_vector(scaled) + instance.position,
+            ),
+            radius: self.radius * scale.x.max(scale.y).max(scale.z),
+        }
+    }
+}
+
+/// The six planes bounding what a camera sees, each facing inwards.
+pub struct Frustum {
+    planes: [cgmath::Vector4<f32>; 6],
+}
+impl Frustum {
+    /// Extracts the planes from a view-projection matrix that maps depth to wgpu's 0..1 range
+    /// (i.e. one that includes `OPENGL_TO_WGPU_MATRIX`).
+    pub fn from_matrix(view_proj: cgmath::Matrix4<f32>) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|i| view_proj.row(i));
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            let length = plane.truncate().magnitude();
+            if length > 0.0 {
+                plane / length
+            } else {
+                plane
+            }
+        });
+        Self { planes }
+    }
+    /// The planes as `ax + by + cz + d`, in the order left, right, bottom, top, near, far.
+    pub fn planes(&self) -> [[f32; 4]; 6] {
+        self.planes.map(Into::into)
+    }
+    /// Whether any part of `sphere` (in world space) may be visible.
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        let center = sphere.center.to_homogeneous();
+        self.planes
+            .iter()
+            .all(|plane| plane.dot(center) >= -sphere.radius)
+    }
+}
+
+/// How many instances the last frame drew and skipped. Meshes of a model that share one
+/// instance list are counted separately.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullingStats {
+    pub visible: u32,
+    pub culled: u32,
+}
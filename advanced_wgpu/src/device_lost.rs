@@ -0,0 +1,87 @@
+//! Noticing that the GPU device was lost, so it can be replaced.
+//!
+//! wgpu has no device-lost callback yet. A lost device shows up in two ways:
+//! - as an uncaptured error from the calls that report errors, like creating a buffer;
+//! - as a panic from the calls that can't, like `Queue::submit`, `Queue::write_buffer`,
+//!   `Device::poll` and `Surface::get_current_texture`.
+//!
+//! Both carry wgpu-core's `DeviceError::Lost`, which reads "parent device is lost": as one of
+//! the error's causes, or after the "Error in <call>: " prefix of the panic message.
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Whether a wgpu error (or one of its causes) says the device was lost.
+pub fn is_device_lost_error(error: &wgpu::Error) -> bool {
+    let mut source: Option<&dyn std::error::Error> = Some(error);
+    while let Some(error) = source {
+        if error.to_string() == DEVICE_LOST {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+/// How wgpu-core's `DeviceError::Lost` displays.
+const DEVICE_LOST: &str = "parent device is lost";
+
+/// Whether a panic is wgpu's fatal "Error in <call>: <cause>" for a lost device.
+fn is_device_lost_panic(message: &str) -> bool {
+    message
+        .strip_prefix("Error in ")
+        .and_then(|message| message.split_once(": "))
+        .map(|(_, cause)| cause)
+        == Some(DEVICE_LOST)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    payload
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| payload.downcast_ref::<&str>().copied())
+}
+
+/// Set once a device's errors or panics say it was lost. Clones share the flag.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceLost(Arc<AtomicBool>);
+impl DeviceLost {
+    /// Watches the uncaptured errors of `device`, replacing its error handler. Only losing the
+    /// device is handled, any other error still panics like wgpu's default handler.
+    pub fn watch(device: &wgpu::Device) -> Self {
+        let lost = Self::default();
+        let flag = lost.clone();
+        device.on_uncaptured_error(move |error| {
+            if is_device_lost_error(&error) {
+                log::error!("Device lost: {}", error);
+                flag.set();
+            } else {
+                panic!("wgpu error: {}\n", error);
+            }
+        });
+        lost
+    }
+    pub fn is_lost(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+    /// Runs `f`, which uses the device. If it panics because the device was lost, the flag is
+    /// set and `None` returned; any other panic carries on unwinding.
+    pub fn catch<T>(&self, f: impl FnOnce() -> T) -> Option<T> {
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(value) => Some(value),
+            Err(payload) => match panic_message(payload.as_ref()) {
+                Some(message) if is_device_lost_panic(message) => {
+                    log::error!("Device lost: {}", message);
+                    self.set();
+                    None
+                }
+                _ => panic::resume_unwind(payload),
+            },
+        }
+    }
+    fn set(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
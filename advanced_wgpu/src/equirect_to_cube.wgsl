@@ -0,0 +1,62 @@
+struct Face {
+    // 0 to 5 for +X, -X, +Y, -Y, +Z and -Z
+    index: u32,
+}
+
+@group(0) @binding(0)
+var t_equirect: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> face: Face;
+
+// The direction a texel of a cube face stands for, with s going right and t down the face
+fn face_direction(s: f32, t: f32) -> vec3<f32> {
+    var direction: vec3<f32>;
+    switch face.index {
+        case 0u: {
+            direction = vec3<f32>(1.0, -t, -s);
+        }
+        case 1u: {
+            direction = vec3<f32>(-1.0, -t, s);
+        }
+        case 2u: {
+            direction = vec3<f32>(s, 1.0, t);
+        }
+        case 3u: {
+            direction = vec3<f32>(s, -1.0, -t);
+        }
+        case 4u: {
+            direction = vec3<f32>(s, -t, 1.0);
+        }
+        default: {
+            direction = vec3<f32>(-s, -t, -1.0);
+        }
+    }
+    return direction;
+}
+
+fn load(texel: vec2<i32>, size: vec2<i32>) -> vec3<f32> {
+    // Wraps around horizontally, clamps at the poles
+    let x = ((texel.x % size.x) + size.x) % size.x;
+    let y = clamp(texel.y, 0, size.y - 1);
+    return textureLoad(t_equirect, vec2<i32>(x, y), 0).rgb;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let st = in.uv * 2.0 - 1.0;
+    let direction = normalize(face_direction(st.x, st.y));
+    let pi = 3.14159265;
+    let uv = vec2<f32>(
+        atan2(direction.z, direction.x) / (2.0 * pi) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / pi,
+    );
+
+    // The float texture can't be filtered by a sampler everywhere, so blend four texels here
+    let size = textureDimensions(t_equirect);
+    let position = uv * vec2<f32>(size) - 0.5;
+    let base = vec2<i32>(floor(position));
+    let f = fract(position);
+    let top = mix(load(base, size), load(base + vec2<i32>(1, 0), size), f.x);
+    let bottom = mix(load(base + vec2<i32>(0, 1), size), load(base + vec2<i32>(1, 1), size), f.x);
+    return vec4<f32>(mix(top, bottom, f.y), 1.0);
+}
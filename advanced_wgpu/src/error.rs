@@ -0,0 +1,44 @@
+use thiserror::Error;
+
+/// Why a [`State`](crate::State) or the window couldn't be set up.
+#[derive(Debug, Error)]
+pub enum InitError {
+    #[error("Failed to create the window")]
+    Window(#[source] winit::error::OsError),
+    #[error(
+        "No {}adapter available for backends {:?}",
+        if *.force_fallback_adapter { "fallback " } else { "" },
+        .backends
+    )]
+    NoAdapter {
+        backends: wgpu::Backends,
+        force_fallback_adapter: bool,
+    },
+    #[error("Failed to get a device from {adapter}")]
+    RequestDevice {
+        adapter: String,
+        #[source]
+        source: wgpu::RequestDeviceError,
+    },
+    #[error("{adapter} can't present to this surface")]
+    UnsupportedSurface { adapter: String },
+    #[error("Failed to decode texture {name}")]
+    Texture {
+        name: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("Shader validation failed: {0}")]
+    Shader(String),
+    #[error("Failed to build the render graph")]
+    RenderGraph(#[source] crate::render_graph::GraphError),
+}
+
+impl InitError {
+    pub(crate) fn texture(name: &str, source: anyhow::Error) -> Self {
+        Self::Texture {
+            name: name.to_string(),
+            source: source.into(),
+        }
+    }
+}
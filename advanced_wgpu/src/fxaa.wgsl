@@ -0,0 +1,65 @@
+struct Fxaa {
+    // Longest distance in texels the edge blur reaches
+    span_max: f32,
+    // How much the blur direction shrinks in bright areas
+    reduce_mul: f32,
+    // Smallest reduction, so dark areas don't blur too far
+    reduce_min: f32,
+    // Set when the surface is sRGB, so edges are found on encoded values
+    srgb_surface: u32,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(0) @binding(2)
+var<uniform> fxaa: Fxaa;
+
+fn tap(uv: vec2<f32>) -> vec3<f32> {
+    let color = clamp(textureSample(t_input, s_input, uv).rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+    if (fxaa.srgb_surface != 0u) {
+        return srgb_encode(color);
+    }
+    return color;
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+// The FXAA 2 console algorithm: blur along the edge found from the four diagonal neighbours
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_input));
+    let input = textureSample(t_input, s_input, in.uv);
+    let luma_nw = luma(tap(in.uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(tap(in.uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(tap(in.uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(tap(in.uv + vec2<f32>(1.0, 1.0) * texel));
+    let luma_m = luma(tap(in.uv));
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var dir = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let dir_reduce = max(
+        (luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * fxaa.reduce_mul,
+        fxaa.reduce_min,
+    );
+    let scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * scale, vec2<f32>(-fxaa.span_max), vec2<f32>(fxaa.span_max)) * texel;
+
+    let inner = 0.5 * (tap(in.uv + dir * (1.0 / 3.0 - 0.5)) + tap(in.uv + dir * (2.0 / 3.0 - 0.5)));
+    let outer = inner * 0.5 + 0.25 * (tap(in.uv - dir * 0.5) + tap(in.uv + dir * 0.5));
+    let luma_outer = luma(outer);
+    // The wider blur crossed another edge, so only the narrow one is used
+    var color = select(outer, inner, luma_outer < luma_min || luma_outer > luma_max);
+
+    if (fxaa.srgb_surface != 0u) {
+        color = srgb_decode(color);
+    }
+    return vec4<f32>(color, input.a);
+}
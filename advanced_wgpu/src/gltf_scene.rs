@@ -0,0 +1,323 @@
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::*;
+use cgmath::prelude::*;
+
+use crate::camera::Projection;
+use crate::model::{self, Material, Mesh, Model};
+use crate::texture;
+use crate::{Camera, Instance, Vertex};
+
+/// A glTF 2.0 scene converted to the crate's types.
+pub struct GltfScene {
+    pub model: Model,
+    /// Every node that references a mesh, grouped so each mesh's instances are contiguous.
+    pub(crate) instances: Vec<Instance>,
+    /// The range of `instances` to draw for each mesh in `model.meshes`.
+    pub mesh_instances: Vec<Range<u32>>,
+    /// The first camera found in the scene, if any.
+    pub(crate) camera: Option<Camera>,
+}
+impl GltfScene {
+    /// Loads a `.gltf` or `.glb` file. Buffers and images may be embedded (data URIs or the
+    /// GLB binary chunk) or sibling files resolved relative to `path`.
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+        layout: &wgpu::BindGroupLayout,
+        aspect: f32,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let (document, buffers, images) = gltf::import(path)
+            .with_context(|| format!("Failed to load glTF file {}", path.display()))?;
+
+        let mut materials = document
+            .materials()
+            .map(|m| {
+                let name = m.name().unwrap_or("material");
+                let pbr = m.pbr_metallic_roughness();
+                // The shader has no material factors, so the base color factor is baked into
+                // the texture instead
+                let factor = pbr.base_color_factor();
+                let diffuse_texture = match pbr.base_color_texture() {
+                    Some(info) => {
+                        let image = &images[info.texture().source().index()];
+                        let mut image = Self::convert_image(image)
+                            .with_context(|| format!("Unsupported base color image in {}", name))?;
+                        if factor != [1.0; 4] {
+                            let mut pixels = image.into_rgba8();
+                            for pixel in pixels.pixels_mut() {
+                                pixel.0 = Self::tint(pixel.0, factor);
+                            }
+                            image = image::DynamicImage::ImageRgba8(pixels);
+                        }
+                        Self::create_texture(device, queue, &image, name, true)?
+                    }
+                    None => {
+                        Material::solid_color_texture(device, queue, Self::tint([255; 4], factor))?
+                    }
+                };
+                let normal_texture = match m.normal_texture() {
+                    Some(info) => {
+                        let image = &images[info.texture().source().index()];
+                        Self::load_texture(device, queue, image, name, false)
+                            .with_context(|| format!("Unsupported normal image in {}", name))?
+                    }
+                    None => Material::flat_normal_texture(device, queue)?,
+                };
+                Ok(Material::new(
+                    device,
+                    name,
+                    diffuse_texture,
+                    normal_texture,
+                    layout,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // Used by primitives without a material, as the glTF spec asks for plain white
+        let default_material = materials.len();
+        materials.push(Material::new(
+            device,
+            "default",
+            Material::solid_color_texture(device, queue, [255, 255, 255, 255])?,
+            Material::flat_normal_texture(device, queue)?,
+            layout,
+        ));
+
+        let mut meshes = Vec::new();
+        // For each glTF mesh, the indices of the model meshes built from its primitives
+        let mut primitive_meshes = Vec::with_capacity(document.meshes().len());
+        for mesh in document.meshes() {
+            let first = meshes.len();
+            for primitive in mesh.primitives() {
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    log::warn!(
+                        "Skipping {:?} primitive in mesh {:?}, only triangles are supported",
+                        primitive.mode(),
+                        mesh.name()
+                    );
+                    continue;
+                }
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let positions = reader
+                    .read_positions()
+                    .with_context(|| format!("Mesh {:?} has no positions", mesh.name()))?;
+                let mut vertices = positions
+                    .map(|position| Vertex {
+                        position,
+                        tex_coords: [0.0, 0.0],
+                        normal: [0.0, 0.0, 0.0],
+                        tangent: [0.0, 0.0, 0.0],
+                        bitangent: [0.0, 0.0, 0.0],
+                    })
+                    .collect::<Vec<_>>();
+                if let Some(tex_coords) = reader.read_tex_coords(0) {
+                    for (vertex, tex_coords) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+                        vertex.tex_coords = tex_coords;
+                    }
+                }
+                let indices = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+                    None => (0..vertices.len() as u32).collect(),
+                };
+                match reader.read_normals() {
+                    Some(normals) => {
+                        for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                            vertex.normal = normal;
+                        }
+                    }
+                    None => model::compute_normals(&mut vertices, &indices),
+                }
+                match reader.read_tangents() {
+                    Some(tangents) => {
+                        for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+                            // The w component gives the handedness of the bitangent
+                            let normal = cgmath::Vector3::from(vertex.normal);
+                            let xyz = cgmath::Vector3::new(tangent[0], tangent[1], tangent[2]);
+                            vertex.tangent = xyz.into();
+                            vertex.bitangent = (normal.cross(xyz) * tangent[3]).into();
+                        }
+                    }
+                    None => model::compute_tangents(&mut vertices, &indices),
+                }
+                let material = primitive.material().index().unwrap_or(default_material);
+
+                meshes.push(Mesh::new(
+                    device,
+                    mesh.name().unwrap_or("mesh"),
+                    &vertices,
+                    &indices,
+                    material,
+                ));
+            }
+            primitive_meshes.push(first..meshes.len());
+        }
+
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .context("glTF file has no scenes")?;
+        let mut mesh_transforms = vec![Vec::new(); document.meshes().len()];
+        let mut camera = None;
+        for node in scene.nodes() {
+            Self::visit_node(
+                &node,
+                cgmath::Matrix4::identity(),
+                aspect,
+                &mut mesh_transforms,
+                &mut camera,
+            );
+        }
+
+        let mut instances = Vec::new();
+        let mut mesh_instances = vec![0..0; meshes.len()];
+        for (transforms, primitives) in mesh_transforms.into_iter().zip(primitive_meshes) {
+            let start = instances.len() as u32;
+            instances.extend(transforms.into_iter().map(Instance::from_matrix));
+            let range = start..instances.len() as u32;
+            for mesh in primitives {
+                mesh_instances[mesh] = range.clone();
+            }
+        }
+
+        Ok(Self {
+            model: Model { meshes, materials },
+            instances,
+            mesh_instances,
+            camera,
+        })
+    }
+    fn visit_node(
+        node: &gltf::Node,
+        parent: cgmath::Matrix4<f32>,
+        aspect: f32,
+        mesh_transforms: &mut Vec<Vec<cgmath::Matrix4<f32>>>,
+        camera: &mut Option<Camera>,
+    ) {
+        let world = parent * cgmath::Matrix4::from(node.transform().matrix());
+
+        if let Some(mesh) = node.mesh() {
+            mesh_transforms[mesh.index()].push(world);
+        }
+        if let (Some(gltf_camera), None) = (node.camera(), camera.as_ref()) {
+            *camera = Some(Self::camera_from_node(&gltf_camera, world, aspect));
+        }
+
+        for child in node.children() {
+            Self::visit_node(&child, world, aspect, mesh_transforms, camera);
+        }
+    }
+    fn camera_from_node(
+        gltf_camera: &gltf::Camera,
+        world: cgmath::Matrix4<f32>,
+        aspect: f32,
+    ) -> Camera {
+        let (projection, fovy, znear, zfar) = match gltf_camera.projection() {
+            gltf::camera::Projection::Perspective(perspective) => (
+                Projection::Perspective,
+                cgmath::Deg::from(cgmath::Rad(perspective.yfov())).0,
+                perspective.znear(),
+                perspective.zfar().unwrap_or(100.),
+            ),
+            // The width comes from the window's aspect ratio rather than xmag
+            gltf::camera::Projection::Orthographic(orthographic) => (
+                Projection::Orthographic {
+                    half_height: orthographic.ymag(),
+                },
+                45.,
+                orthographic.znear(),
+                orthographic.zfar(),
+            ),
+        };
+
+        // glTF cameras look down their local -Z axis with +Y up
+        let eye = cgmath::Point3::from_vec(world.w.truncate());
+        let forward = -world.z.truncate().normalize();
+        Camera {
+            eye,
+            target: eye + forward,
+            up: world.y.truncate().normalize(),
+            aspect,
+            projection,
+            fovy,
+            znear,
+            zfar,
+        }
+    }
+    fn load_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &gltf::image::Data,
+        name: &str,
+        srgb: bool,
+    ) -> Result<texture::Texture> {
+        let image = Self::convert_image(data).context("Unsupported image format")?;
+        Self::create_texture(device, queue, &image, name, srgb)
+    }
+    fn create_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::DynamicImage,
+        name: &str,
+        srgb: bool,
+    ) -> Result<texture::Texture> {
+        texture::Texture::from_image_with_options(
+            device,
+            queue,
+            image,
+            Some(name),
+            texture::TextureOptions {
+                generate_mipmaps: true,
+                filtering: texture::Filtering::Anisotropic(16),
+                srgb,
+            },
+        )
+    }
+    /// Multiplies an sRGB-encoded color by a linear glTF color factor. Alpha is linear.
+    fn tint(color: [u8; 4], factor: [f32; 4]) -> [u8; 4] {
+        let mut tinted = [0; 4];
+        for (i, (&c, f)) in color.iter().zip(factor).enumerate() {
+            let c = c as f32 / 255.0;
+            let c = if i == 3 {
+                c * f
+            } else {
+                srgb_encode(srgb_decode(c) * f)
+            };
+            tinted[i] = (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        tinted
+    }
+    fn convert_image(data: &gltf::image::Data) -> Option<image::DynamicImage> {
+        use gltf::image::Format;
+
+        let (width, height, pixels) = (data.width, data.height, data.pixels.clone());
+        match data.format {
+            Format::R8G8B8A8 => image::RgbaImage::from_raw(width, height, pixels)
+                .map(image::DynamicImage::ImageRgba8),
+            Format::R8G8B8 => {
+                image::RgbImage::from_raw(width, height, pixels).map(image::DynamicImage::ImageRgb8)
+            }
+            Format::R8 => image::GrayImage::from_raw(width, height, pixels)
+                .map(image::DynamicImage::ImageLuma8),
+            _ => None,
+        }
+    }
+}
+
+fn srgb_decode(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn srgb_encode(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -0,0 +1,234 @@
+use wgpu::util::DeviceExt;
+
+use crate::{pipeline, post, render_graph};
+
+/// Format of the scene target, so lighting and emissive surfaces can exceed 1.0.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// How the HDR scene is mapped into the 0 to 1 range of the surface.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tonemapping {
+    /// Clips everything above 1.0, like rendering straight into the surface.
+    Clamp,
+    /// `color / (1 + color)`, which never clips but flattens highlights.
+    Reinhard,
+    /// An approximation of the ACES filmic curve, with more contrast than Reinhard.
+    #[default]
+    Aces,
+}
+
+/// Tonemapping parameters for the HDR scene target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TonemapSettings {
+    pub tonemapping: Tonemapping,
+    /// Scales the scene's colors before tonemapping.
+    pub exposure: f32,
+    /// Display gamma the output is encoded for: 2.2 gives plain sRGB, higher values brighten
+    /// the midtones.
+    pub gamma: f32,
+}
+impl Default for TonemapSettings {
+    fn default() -> Self {
+        Self {
+            tonemapping: Tonemapping::default(),
+            exposure: 1.0,
+            gamma: 2.2,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapUniform {
+    exposure: f32,
+    gamma: f32,
+    tonemapping: u32,
+    encode_srgb: u32,
+}
+
+/// The fullscreen pass that tonemaps the `HDR_FORMAT` scene into the surface. The render graph
+/// allocates the scene texture, see [`Self::scene_texture`].
+pub(crate) struct HdrTarget {
+    settings: TonemapSettings,
+    /// Whether the surface format isn't sRGB, so the pass encodes the output itself.
+    encode_srgb: bool,
+    buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Reads the scene, set by `bind`.
+    bind_group: Option<wgpu::BindGroup>,
+    pipeline: pipeline::PipelineId,
+}
+impl HdrTarget {
+    /// Whether the adapter can render into `HDR_FORMAT` and sample it, which WebGL2 can't
+    /// without `EXT_color_buffer_float`.
+    pub fn is_supported(adapter: &wgpu::Adapter) -> bool {
+        adapter
+            .get_texture_format_features(HDR_FORMAT)
+            .allowed_usages
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)
+    }
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        pipelines: &mut pipeline::PipelineCache,
+        settings: TonemapSettings,
+    ) -> Self {
+        let encode_srgb = !config.format.describe().srgb;
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tonemap Buffer"),
+            contents: bytemuck::cast_slice(&[Self::uniform(&settings, encode_srgb)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        // Only read with textureLoad
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("tonemap_bind_group_layout"),
+        });
+        pipelines.add_layout(
+            "tonemap",
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Tonemap Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            }),
+        );
+        let pipeline = pipelines.get_or_create(
+            device,
+            &pipeline::PipelineDesc::new(
+                "Tonemap Pipeline",
+                post::fullscreen_shader(include_str!("tonemap.wgsl")),
+                "tonemap",
+                config.format,
+            )
+            .cull_mode(None),
+        );
+
+        Self {
+            settings,
+            encode_srgb,
+            buffer,
+            bind_group_layout,
+            bind_group: None,
+            pipeline,
+        }
+    }
+    /// The texture the scene is drawn into, for the render graph to allocate.
+    pub fn scene_texture() -> render_graph::TextureDesc {
+        render_graph::TextureDesc::new(
+            "hdr_target",
+            HDR_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                // Bloom starts from a copy of the scene
+                | wgpu::TextureUsages::COPY_SRC,
+        )
+    }
+    pub fn settings(&self) -> TonemapSettings {
+        self.settings
+    }
+    pub fn set_settings(&mut self, queue: &wgpu::Queue, settings: TonemapSettings) {
+        self.settings = settings;
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&[Self::uniform(&settings, self.encode_srgb)]),
+        );
+    }
+    /// Tonemaps `scene_view` from then on. The render graph replaces its textures when it's
+    /// compiled or resized, so this has to be called again after that.
+    pub fn bind(&mut self, device: &wgpu::Device, scene_view: &wgpu::TextureView) {
+        self.bind_group = Some(Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            scene_view,
+            &self.buffer,
+        ));
+    }
+    /// Tonemaps the scene texture into `view`, which has the surface format.
+    ///
+    /// # Panics
+    ///
+    /// If no scene texture was bound with [`Self::bind`].
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipelines: &pipeline::PipelineCache,
+        view: &wgpu::TextureView,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tonemap Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    // Every pixel is overwritten
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&pipelines[self.pipeline]);
+        let bind_group = self
+            .bind_group
+            .as_ref()
+            .expect("HdrTarget::draw before bind");
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+    fn uniform(settings: &TonemapSettings, encode_srgb: bool) -> TonemapUniform {
+        TonemapUniform {
+            exposure: settings.exposure,
+            gamma: settings.gamma,
+            tonemapping: match settings.tonemapping {
+                Tonemapping::Clamp => 0,
+                Tonemapping::Reinhard => 1,
+                Tonemapping::Aces => 2,
+            },
+            encode_srgb: encode_srgb as u32,
+        }
+    }
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        view: &wgpu::TextureView,
+        buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("tonemap_bind_group"),
+        })
+    }
+}
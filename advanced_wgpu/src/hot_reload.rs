@@ -0,0 +1,159 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::pipeline::{self, PipelineCache};
+
+/// How often the shader files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+struct WatchedShader {
+    path: PathBuf,
+    /// A file put in front of the shader, see [`ShaderWatcher::watch_with_prelude`].
+    prelude: Option<PathBuf>,
+    /// The last change to the shader or its prelude.
+    modified: Option<SystemTime>,
+    /// The source the pipelines are currently built from.
+    source: Cow<'static, str>,
+    /// The source given to [`ShaderWatcher::watch`].
+    original: Cow<'static, str>,
+}
+
+/// Watches WGSL files on disk and rebuilds the pipelines that use them when they change.
+/// Shaders that fail to parse or validate are logged and the last good pipelines are kept.
+pub struct ShaderWatcher {
+    shaders: Vec<WatchedShader>,
+    last_poll: Instant,
+}
+impl ShaderWatcher {
+    pub fn new() -> Self {
+        Self {
+            shaders: Vec::new(),
+            last_poll: Instant::now(),
+        }
+    }
+    /// Watches `path`, whose contents the pipelines were built from as `source`.
+    pub fn watch(&mut self, path: impl Into<PathBuf>, source: impl Into<Cow<'static, str>>) {
+        self.push(path.into(), None, source.into());
+    }
+    /// Watches `path` and the `prelude` it's loaded with (see [`pipeline::with_prelude`]),
+    /// whose contents the pipelines were built from as `source`. Changing either reloads it;
+    /// diagnostics count lines from the start of the prelude.
+    pub fn watch_with_prelude(
+        &mut self,
+        path: impl Into<PathBuf>,
+        prelude: impl Into<PathBuf>,
+        source: impl Into<Cow<'static, str>>,
+    ) {
+        self.push(path.into(), Some(prelude.into()), source.into());
+    }
+    fn push(&mut self, path: PathBuf, prelude: Option<PathBuf>, source: Cow<'static, str>) {
+        let mut shader = WatchedShader {
+            path,
+            prelude,
+            modified: None,
+            original: source.clone(),
+            source,
+        };
+        shader.modified = shader.modified();
+        self.shaders.push(shader);
+    }
+    /// Call after the pipelines were rebuilt from the original sources (e.g. on a new
+    /// device), so the next poll applies the files on disk again.
+    pub fn pipelines_rebuilt(&mut self) {
+        for shader in &mut self.shaders {
+            shader.source = shader.original.clone();
+            shader.modified = None;
+        }
+    }
+    /// Reloads the shaders that changed since the last poll. Cheap enough to call every frame.
+    pub fn poll(&mut self, device: &wgpu::Device, pipelines: &mut PipelineCache) {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return;
+        }
+        self.last_poll = Instant::now();
+
+        for shader in &mut self.shaders {
+            let modified = shader.modified();
+            if modified == shader.modified {
+                continue;
+            }
+            shader.modified = modified;
+
+            let source = match shader.read() {
+                Ok(source) => source,
+                Err(err) => {
+                    log::warn!("Couldn't read shader {}: {}", shader.path.display(), err);
+                    continue;
+                }
+            };
+            if source == shader.source {
+                continue;
+            }
+            if let Err(diagnostic) = validate(&shader.path, &source) {
+                log::error!("Not reloading {}:\n{}", shader.path.display(), diagnostic);
+                continue;
+            }
+
+            match pipelines.replace_shader(device, &shader.source, source.clone().into()) {
+                Ok(count) => {
+                    log::info!("Reloaded {} ({} pipelines)", shader.path.display(), count);
+                    shader.source = source.into();
+                }
+                Err(err) => log::error!("Not reloading {}: {}", shader.path.display(), err),
+            }
+        }
+    }
+}
+impl WatchedShader {
+    fn modified(&self) -> Option<SystemTime> {
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let prelude = self.prelude.as_deref().and_then(modified);
+        modified(&self.path).max(prelude)
+    }
+    /// The shader's source as the pipelines are built from it, prelude included.
+    fn read(&self) -> std::io::Result<String> {
+        let source = std::fs::read_to_string(&self.path)?;
+        match &self.prelude {
+            Some(prelude) => Ok(pipeline::with_prelude(
+                &std::fs::read_to_string(prelude)?,
+                &source,
+            )),
+            None => Ok(source),
+        }
+    }
+}
+impl Default for ShaderWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses and validates WGSL with naga, returning its diagnostic on failure.
+pub fn validate(path: &Path, source: &str) -> Result<(), String> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|err| err.emit_to_string(source))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    )
+    .validate(&module)
+    .map_err(|err| {
+        let mut diagnostic = match err.location(source) {
+            Some(location) => format!(
+                "{}:{}:{}: {}",
+                path.display(),
+                location.line_number,
+                location.line_position,
+                err
+            ),
+            None => format!("{}: {}", path.display(), err),
+        };
+        let mut cause = std::error::Error::source(&err);
+        while let Some(err) = cause {
+            diagnostic.push_str(&format!("\n  caused by: {}", err));
+            cause = err.source();
+        }
+        diagnostic
+    })?;
+    Ok(())
+}
@@ -0,0 +1,427 @@
+use std::ops::Range;
+
+use crate::culling::{BoundingSphere, CullingStats, Frustum};
+use crate::{Instance, InstanceRaw};
+
+const WORKGROUP_SIZE: u32 = 64;
+/// Size of one `DrawIndexedIndirect`: five 32-bit values.
+const DRAW_SIZE: wgpu::BufferAddress = 20;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    planes: [[f32; 4]; 6],
+    spin: [f32; 4],
+    instance_count: u32,
+    max_group_instances: u32,
+    group_count: u32,
+    cull_enabled: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuInstance {
+    position: [f32; 4],
+    /// Quaternion with the scalar part in w.
+    rotation: [f32; 4],
+    scale: [f32; 4],
+}
+impl GpuInstance {
+    fn new(instance: &Instance) -> Self {
+        Self {
+            position: instance.position.extend(1.0).into(),
+            rotation: [
+                instance.rotation.v.x,
+                instance.rotation.v.y,
+                instance.rotation.v.z,
+                instance.rotation.s,
+            ],
+            scale: instance.scale.extend(1.0).into(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuGroup {
+    bounds: [f32; 4],
+    instance_start: u32,
+    instance_count: u32,
+    first: u32,
+    _padding: u32,
+}
+
+/// A mesh, its bounds and the range of instances it is drawn with.
+#[derive(Clone, Debug)]
+pub(crate) struct InstanceGroup {
+    pub bounds: BoundingSphere,
+    pub instances: Range<u32>,
+    pub index_count: u32,
+}
+
+/// Keeps the instances in a storage buffer and spins and frustum-culls them with compute
+/// shaders, so drawing them takes one `draw_indexed_indirect` per group and no uploads.
+/// Needs compute shaders and indirect draws, which WebGL2 doesn't have.
+pub(crate) struct GpuInstances {
+    bind_group_layout: wgpu::BindGroupLayout,
+    animate_pipeline: wgpu::ComputePipeline,
+    cull_pipeline: wgpu::ComputePipeline,
+    params_buffer: wgpu::Buffer,
+    /// Buffers sized for the current instances and groups, `None` until [`Self::upload`].
+    buffers: Option<Buffers>,
+    /// Spin applied to the uploaded rotations, in radians around z.
+    spin_angle: f32,
+}
+struct Buffers {
+    instances: wgpu::Buffer,
+    instance_count: u32,
+    groups: Vec<GpuGroup>,
+    /// The `DrawIndexedIndirect` arguments with no instances, written before each cull.
+    initial_draws: Vec<u8>,
+    /// Every instance after the spin, for passes that aren't culled (e.g. shadows).
+    transformed: wgpu::Buffer,
+    /// The visible instances of each group, starting at the group's `first`.
+    visible: wgpu::Buffer,
+    draws: wgpu::Buffer,
+    /// A copy of `draws` after each cull, mapped by [`GpuInstances::read_stats`].
+    stats_readback: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+impl GpuInstances {
+    /// Whether the adapter can run the compute path.
+    pub fn is_supported(adapter: &wgpu::Adapter) -> bool {
+        let flags = adapter.get_downlevel_capabilities().flags;
+        flags.contains(
+            wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION,
+        ) && adapter.limits().max_storage_buffers_per_shader_stage >= 5
+    }
+    pub fn new(device: &wgpu::Device) -> Self {
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, false),
+                storage(4, false),
+                storage(5, false),
+            ],
+            label: Some("instance_compute_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Instance Compute Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Instance Compute Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("instance_compute.wgsl").into()),
+        });
+        let pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+        let animate_pipeline = pipeline("Instance Animate Pipeline", "animate");
+        let cull_pipeline = pipeline("Instance Cull Pipeline", "cull");
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Compute Params"),
+            size: std::mem::size_of::<Params>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            bind_group_layout,
+            animate_pipeline,
+            cull_pipeline,
+            params_buffer,
+            buffers: None,
+            spin_angle: 0.0,
+        }
+    }
+    /// Replaces the instances and groups, resetting the spin.
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        instances: &[Instance],
+        groups: &[InstanceGroup],
+    ) {
+        use wgpu::util::DeviceExt;
+
+        let instance_data = instances.iter().map(GpuInstance::new).collect::<Vec<_>>();
+        let mut first = 0;
+        let group_data = groups
+            .iter()
+            .map(|group| {
+                let gpu_group = GpuGroup {
+                    bounds: [
+                        group.bounds.center.x,
+                        group.bounds.center.y,
+                        group.bounds.center.z,
+                        group.bounds.radius,
+                    ],
+                    instance_start: group.instances.start,
+                    instance_count: group.instances.len() as u32,
+                    first,
+                    _padding: 0,
+                };
+                first += gpu_group.instance_count;
+                gpu_group
+            })
+            .collect::<Vec<_>>();
+        let draw_data = groups
+            .iter()
+            .flat_map(|group| {
+                wgpu::util::DrawIndexedIndirect {
+                    vertex_count: group.index_count,
+                    instance_count: 0,
+                    base_index: 0,
+                    vertex_offset: 0,
+                    base_instance: 0,
+                }
+                .as_bytes()
+                .to_vec()
+            })
+            .collect::<Vec<_>>();
+
+        // Storage bindings can't be empty
+        let instance_size = std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress;
+        let storage_buffer = |label, contents: &[u8]| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: if contents.is_empty() {
+                    &[0; 32]
+                } else {
+                    contents
+                },
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            })
+        };
+        let output_buffer = |label, count: u32| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: instance_size * count.max(1) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+                mapped_at_creation: false,
+            })
+        };
+        let instance_buffer = storage_buffer(
+            "Compute Instance Buffer",
+            bytemuck::cast_slice(&instance_data),
+        );
+        let group_buffer =
+            storage_buffer("Compute Group Buffer", bytemuck::cast_slice(&group_data));
+        let transformed = output_buffer("Transformed Instance Buffer", instances.len() as u32);
+        let visible = output_buffer("Compute Visible Instance Buffer", first);
+        let draws = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Indirect Draw Buffer"),
+            contents: if draw_data.is_empty() {
+                &[0; DRAW_SIZE as usize]
+            } else {
+                &draw_data
+            },
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        });
+        let stats_readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Indirect Draw Readback Buffer"),
+            size: groups.len().max(1) as wgpu::BufferAddress * DRAW_SIZE,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: instance_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: group_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: transformed.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: visible.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: draws.as_entire_binding(),
+                },
+            ],
+            label: Some("instance_compute_bind_group"),
+        });
+
+        self.buffers = Some(Buffers {
+            instances: instance_buffer,
+            instance_count: instances.len() as u32,
+            groups: group_data,
+            initial_draws: draw_data,
+            transformed,
+            visible,
+            draws,
+            stats_readback,
+            bind_group,
+        });
+        self.spin_angle = 0.0;
+    }
+    /// Rewrites the uploaded instances in place, keeping the groups. There must be as many
+    /// instances as were uploaded.
+    pub fn write_instances(&self, queue: &wgpu::Queue, instances: &[Instance]) {
+        if let Some(buffers) = &self.buffers {
+            debug_assert_eq!(instances.len(), buffers.instance_count as usize);
+            if !instances.is_empty() {
+                let instance_data = instances.iter().map(GpuInstance::new).collect::<Vec<_>>();
+                queue.write_buffer(&buffers.instances, 0, bytemuck::cast_slice(&instance_data));
+            }
+        }
+    }
+    /// Adds to the spin around z of every instance.
+    pub fn spin(&mut self, angle: cgmath::Rad<f32>) {
+        self.spin_angle = (self.spin_angle + angle.0) % std::f32::consts::TAU;
+    }
+    /// Returns the spin applied since the last upload and resets it, for handing the
+    /// instances back to the CPU. The next [`Self::upload`] must include that spin.
+    pub fn take_spin(&mut self) -> cgmath::Rad<f32> {
+        cgmath::Rad(std::mem::take(&mut self.spin_angle))
+    }
+    /// Spins all instances and culls them against `frustum` (or keeps them all if `None`),
+    /// resetting the indirect draws first.
+    pub fn dispatch(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        frustum: Option<&Frustum>,
+    ) {
+        let Some(buffers) = &self.buffers else {
+            return;
+        };
+        let max_group_instances = buffers
+            .groups
+            .iter()
+            .map(|group| group.instance_count)
+            .max()
+            .unwrap_or(0);
+        let (spin_sin, spin_cos) = (self.spin_angle * 0.5).sin_cos();
+        let params = Params {
+            planes: frustum.map_or([[0.0; 4]; 6], |frustum| frustum.planes()),
+            spin: [0.0, 0.0, spin_sin, spin_cos],
+            instance_count: buffers.instance_count,
+            max_group_instances,
+            group_count: buffers.groups.len() as u32,
+            cull_enabled: frustum.is_some() as u32,
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+        if !buffers.initial_draws.is_empty() {
+            queue.write_buffer(&buffers.draws, 0, &buffers.initial_draws);
+        }
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Instance Compute Pass"),
+        });
+        compute_pass.set_bind_group(0, &buffers.bind_group, &[]);
+        compute_pass.set_pipeline(&self.animate_pipeline);
+        compute_pass.dispatch_workgroups(buffers.instance_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        compute_pass.set_pipeline(&self.cull_pipeline);
+        compute_pass.dispatch_workgroups(
+            max_group_instances.div_ceil(WORKGROUP_SIZE),
+            buffers.groups.len() as u32,
+            1,
+        );
+        drop(compute_pass);
+        encoder.copy_buffer_to_buffer(
+            &buffers.draws,
+            0,
+            &buffers.stats_readback,
+            0,
+            buffers.groups.len().max(1) as wgpu::BufferAddress * DRAW_SIZE,
+        );
+    }
+    /// Reads back how many instances the last [`Self::dispatch`] kept and culled. Waits for
+    /// the GPU to finish that dispatch, so it's meant for debugging and tests rather than
+    /// every frame.
+    pub fn read_stats(&self, device: &wgpu::Device) -> CullingStats {
+        let Some(buffers) = &self.buffers else {
+            return CullingStats::default();
+        };
+        let slice = buffers.stats_readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| ());
+        device.poll(wgpu::Maintain::Wait);
+        let visible = {
+            let draws = slice.get_mapped_range();
+            // `instance_count` is the second value of each `DrawIndexedIndirect`
+            draws
+                .chunks_exact(DRAW_SIZE as usize)
+                .take(buffers.groups.len())
+                .map(|draw| u32::from_le_bytes(draw[4..8].try_into().unwrap()))
+                .sum::<u32>()
+        };
+        buffers.stats_readback.unmap();
+        let total = buffers
+            .groups
+            .iter()
+            .map(|group| group.instance_count)
+            .sum::<u32>();
+        CullingStats {
+            visible,
+            culled: total.saturating_sub(visible),
+        }
+    }
+    /// Every instance after the spin, indexed like the uploaded instances.
+    pub fn transformed_buffer(&self) -> Option<&wgpu::Buffer> {
+        self.buffers.as_ref().map(|buffers| &buffers.transformed)
+    }
+    /// The instance buffer slice to draw `group` with, and the buffer and offset of its
+    /// `DrawIndexedIndirect` arguments. `None` if the group has no instances at all.
+    pub fn group_draw(
+        &self,
+        group: usize,
+    ) -> Option<(wgpu::BufferSlice<'_>, &wgpu::Buffer, wgpu::BufferAddress)> {
+        let buffers = self.buffers.as_ref()?;
+        let gpu_group = buffers.groups.get(group)?;
+        if gpu_group.instance_count == 0 {
+            return None;
+        }
+        let first = gpu_group.first as wgpu::BufferAddress;
+        let instance_size = std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress;
+        Some((
+            buffers.visible.slice(first * instance_size..),
+            &buffers.draws,
+            group as wgpu::BufferAddress * DRAW_SIZE,
+        ))
+    }
+}
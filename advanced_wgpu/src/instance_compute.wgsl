@@ -0,0 +1,143 @@
+// Spins the instances and frustum-culls them on the GPU, writing `DrawIndexedIndirect`
+// arguments so the instance counts never have to come back to the CPU.
+
+struct Params {
+    // Inward-facing frustum planes, normalized
+    planes: array<vec4<f32>, 6>,
+    // Quaternion applied to every instance's rotation
+    spin: vec4<f32>,
+    instance_count: u32,
+    // Largest instance count of any group, the width of the `cull` dispatch
+    max_group_instances: u32,
+    group_count: u32,
+    cull_enabled: u32,
+}
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+struct Instance {
+    position: vec4<f32>,
+    rotation: vec4<f32>,
+    scale: vec4<f32>,
+}
+@group(0) @binding(1)
+var<storage, read> instances: array<Instance>;
+
+// A mesh and the instances drawn with it
+struct Group {
+    // Bounding sphere center in xyz, radius in w
+    bounds: vec4<f32>,
+    instance_start: u32,
+    instance_count: u32,
+    // Where this group's visible instances start in `visible`
+    first: u32,
+    _padding: u32,
+}
+@group(0) @binding(2)
+var<storage, read> groups: array<Group>;
+
+// `InstanceRaw`s: a 4x4 model matrix followed by a 3x3 normal matrix, column by column
+let INSTANCE_RAW_FLOATS: u32 = 25u;
+@group(0) @binding(3)
+var<storage, read_write> transformed: array<f32>;
+@group(0) @binding(4)
+var<storage, read_write> visible: array<f32>;
+
+struct DrawArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+@group(0) @binding(5)
+var<storage, read_write> draws: array<DrawArgs>;
+
+fn quat_mul(a: vec4<f32>, b: vec4<f32>) -> vec4<f32> {
+    return vec4<f32>(
+        a.w * b.xyz + b.w * a.xyz + cross(a.xyz, b.xyz),
+        a.w * b.w - dot(a.xyz, b.xyz),
+    );
+}
+
+fn quat_to_mat3(q: vec4<f32>) -> mat3x3<f32> {
+    let x2 = q.x + q.x;
+    let y2 = q.y + q.y;
+    let z2 = q.z + q.z;
+    let xx = q.x * x2;
+    let yy = q.y * y2;
+    let zz = q.z * z2;
+    let xy = q.x * y2;
+    let xz = q.x * z2;
+    let yz = q.y * z2;
+    let wx = q.w * x2;
+    let wy = q.w * y2;
+    let wz = q.w * z2;
+    return mat3x3<f32>(
+        vec3<f32>(1.0 - yy - zz, xy + wz, xz - wy),
+        vec3<f32>(xy - wz, 1.0 - xx - zz, yz + wx),
+        vec3<f32>(xz + wy, yz - wx, 1.0 - xx - yy),
+    );
+}
+
+@compute @workgroup_size(64)
+fn animate(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if (i >= params.instance_count) {
+        return;
+    }
+    let instance = instances[i];
+    // A variable so it can be indexed dynamically
+    var rotation = quat_to_mat3(quat_mul(params.spin, instance.rotation));
+
+    let base = i * INSTANCE_RAW_FLOATS;
+    // The normal matrix divides by the scale instead (by 1 for a zero scale), see
+    // `Instance::to_raw`
+    var scale = instance.scale;
+    var inverse_scale = select(1.0 / scale, vec4<f32>(1.0), scale == vec4<f32>(0.0));
+    for (var column = 0u; column < 3u; column = column + 1u) {
+        for (var row = 0u; row < 3u; row = row + 1u) {
+            transformed[base + column * 4u + row] = rotation[column][row] * scale[column];
+            transformed[base + 16u + column * 3u + row] = rotation[column][row] * inverse_scale[column];
+        }
+        transformed[base + column * 4u + 3u] = 0.0;
+    }
+    transformed[base + 12u] = instance.position.x;
+    transformed[base + 13u] = instance.position.y;
+    transformed[base + 14u] = instance.position.z;
+    transformed[base + 15u] = 1.0;
+}
+
+// One row of invocations per group: x indexes the group's instances, y the group
+@compute @workgroup_size(64)
+fn cull(@builtin(global_invocation_id) id: vec3<u32>) {
+    let g = id.y;
+    if (g >= params.group_count || id.x >= groups[g].instance_count) {
+        return;
+    }
+    let group = groups[g];
+    let source = (group.instance_start + id.x) * INSTANCE_RAW_FLOATS;
+
+    if (params.cull_enabled != 0u) {
+        let model = mat4x4<f32>(
+            vec4<f32>(transformed[source], transformed[source + 1u], transformed[source + 2u], transformed[source + 3u]),
+            vec4<f32>(transformed[source + 4u], transformed[source + 5u], transformed[source + 6u], transformed[source + 7u]),
+            vec4<f32>(transformed[source + 8u], transformed[source + 9u], transformed[source + 10u], transformed[source + 11u]),
+            vec4<f32>(transformed[source + 12u], transformed[source + 13u], transformed[source + 14u], transformed[source + 15u]),
+        );
+        let center = model * vec4<f32>(group.bounds.xyz, 1.0);
+        let scale = max(length(model[0].xyz), max(length(model[1].xyz), length(model[2].xyz)));
+        let radius = group.bounds.w * scale;
+        for (var p = 0u; p < 6u; p = p + 1u) {
+            if (dot(params.planes[p], center) < -radius) {
+                return;
+            }
+        }
+    }
+
+    let slot = atomicAdd(&draws[g].instance_count, 1u);
+    let destination = (group.first + slot) * INSTANCE_RAW_FLOATS;
+    for (var f = 0u; f < INSTANCE_RAW_FLOATS; f = f + 1u) {
+        visible[destination + f] = transformed[source + f];
+    }
+}
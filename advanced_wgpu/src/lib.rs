@@ -1,14 +1,40 @@
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    window::{Fullscreen, Window, WindowBuilder},
 };
 
 use wgpu::util::DeviceExt;
 
 use cgmath::prelude::*;
 
-mod texture;
+use model::DrawModel;
+
+use anyhow::{bail, Context, Result};
+
+use std::sync::Arc;
+
+mod camera;
+pub mod config;
+pub use camera::Projection;
+pub mod culling;
+pub mod device_lost;
+mod error;
+pub use error::InitError;
+pub mod gltf_scene;
+pub mod hdr;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod hot_reload;
+mod instance_compute;
+pub mod model;
+pub use wgpu_pipeline as pipeline;
+pub mod post;
+pub mod render_graph;
+pub mod scene;
+pub mod scene_file;
+pub mod shadow;
+mod skybox;
+pub mod texture;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
@@ -18,6 +44,9 @@ use wasm_bindgen::prelude::*;
 struct Vertex {
     position: [f32; 3],
     tex_coords: [f32; 2],
+    normal: [f32; 3],
+    tangent: [f32; 3],
+    bitangent: [f32; 3],
 }
 impl Vertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
@@ -35,34 +64,105 @@ impl Vertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
             ],
         }
     }
+    /// Copies `vertices` with tangents and bitangents generated from `indices`.
+    fn with_tangents<I: Copy + Into<u32>>(vertices: &[Vertex], indices: &[I]) -> Vec<Vertex> {
+        let mut vertices = vertices.to_vec();
+        model::compute_tangents(&mut vertices, indices);
+        vertices
+    }
 }
 
 struct Instance {
     position: cgmath::Vector3<f32>,
     rotation: cgmath::Quaternion<f32>,
+    scale: cgmath::Vector3<f32>,
 }
 impl Instance {
+    /// Splits an affine transform into translation, rotation and scale. Shear (from
+    /// non-uniform scale above a rotation) can't be represented and is lost.
+    fn from_matrix(matrix: cgmath::Matrix4<f32>) -> Self {
+        let mut x = matrix.x.truncate();
+        let y = matrix.y.truncate();
+        let z = matrix.z.truncate();
+        let mut scale = cgmath::Vector3::new(x.magnitude(), y.magnitude(), z.magnitude());
+        // A mirrored transform has no rotation, so flip one axis back
+        if x.cross(y).dot(z) < 0. {
+            x = -x;
+            scale.x = -scale.x;
+        }
+        // A zero scale (e.g. to hide a node) leaves that axis without a direction, so it's
+        // rebuilt from the other two; with more than one missing there's no rotation left
+        let unit = |axis: cgmath::Vector3<f32>, length: f32| {
+            (length.abs() > f32::EPSILON).then(|| axis / length.abs())
+        };
+        let axes = match (unit(x, scale.x), unit(y, scale.y), unit(z, scale.z)) {
+            (Some(x), Some(y), Some(z)) => Some((x, y, z)),
+            (None, Some(y), Some(z)) => Some((y.cross(z).normalize(), y, z)),
+            (Some(x), None, Some(z)) => Some((x, z.cross(x).normalize(), z)),
+            (Some(x), Some(y), None) => Some((x, y, x.cross(y).normalize())),
+            _ => None,
+        };
+        let rotation = axes.map_or(cgmath::Quaternion::one(), |(x, y, z)| {
+            cgmath::Quaternion::from(cgmath::Matrix3::from_cols(x, y, z)).normalize()
+        });
+        Self {
+            position: matrix.w.truncate(),
+            rotation,
+            scale,
+        }
+    }
     fn to_raw(&self) -> InstanceRaw {
+        let rotation = cgmath::Matrix3::from(self.rotation);
+        let scale = cgmath::Matrix3::from_diagonal(self.scale);
         InstanceRaw {
             model: (cgmath::Matrix4::from_translation(self.position)
-                * cgmath::Matrix4::from(self.rotation))
+                * cgmath::Matrix4::from(rotation * scale))
             .into(),
+            // The inverse transpose of the model matrix keeps normals perpendicular to
+            // non-uniformly scaled surfaces. Flattened axes keep their normals unscaled.
+            normal: (rotation * cgmath::Matrix3::from_diagonal(self.scale.map(inverse_scale)))
+                .into(),
         }
     }
-    fn spin(&mut self) {
-        let amount = cgmath::Quaternion::from_angle_z(cgmath::Rad(0.01));
+    fn spin(&mut self, angle: cgmath::Rad<f32>) {
+        let amount = cgmath::Quaternion::from_angle_z(angle);
         let current = self.rotation;
         self.rotation = amount * current;
     }
 }
 
+/// `1 / scale`, or 1 for a zero scale, which has no inverse.
+fn inverse_scale(scale: f32) -> f32 {
+    if scale == 0. {
+        1.
+    } else {
+        1. / scale
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct InstanceRaw {
     model: [[f32; 4]; 4],
+    normal: [[f32; 3]; 3],
 }
 impl InstanceRaw {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
@@ -91,6 +191,21 @@ impl InstanceRaw {
                     shader_location: 8,
                     format: wgpu::VertexFormat::Float32x4,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 19]>() as wgpu::BufferAddress,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 22]>() as wgpu::BufferAddress,
+                    shader_location: 11,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
             ],
         }
     }
@@ -100,22 +215,37 @@ const VERTICES: &[Vertex] = &[
     Vertex {
         position: [-0.0868241, 0.49240386, 0.0],
         tex_coords: [0.4131759, 0.00759614],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     }, // A
     Vertex {
         position: [-0.49513406, 0.06958647, 0.0],
         tex_coords: [0.0048659444, 0.43041354],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     }, // B
     Vertex {
         position: [-0.21918549, -0.44939706, 0.0],
         tex_coords: [0.28081453, 0.949397],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     }, // C
     Vertex {
         position: [0.35966998, -0.3473291, 0.0],
         tex_coords: [0.85967, 0.84732914],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     }, // D
     Vertex {
         position: [0.44147372, 0.2347359, 0.0],
         tex_coords: [0.9414737, 0.2652641],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     }, // E
 ];
 const INDICES: &[u16] = &[0, 1, 4, 1, 2, 4, 2, 3, 4, /* padding */ 0];
@@ -125,81 +255,129 @@ const VERTICES_CHAL: &[Vertex] = &[
         // A - top left
         position: [-0.707, 0.707, 0.],
         tex_coords: [0., 0.],
+        normal: [0., 0., 1.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // B - bottom left
         position: [-0.707, -0.707, 0.],
         tex_coords: [0., 1.],
+        normal: [0., 0., 1.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // C - bottom right
         position: [0.707, -0.707, 0.],
         tex_coords: [0.5, 1.],
+        normal: [0., 0., 1.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // D - top right
         position: [0.707, 0.707, 0.],
         tex_coords: [0.5, 0.],
+        normal: [0., 0., 1.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // E - top right - left face
         position: [0.707, 0.707, -1.414],
         tex_coords: [0., 0.],
+        normal: [1., 0., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // F - bottom right - left face
         position: [0.707, -0.707, -1.414],
         tex_coords: [0., 1.],
+        normal: [1., 0., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // G - top left - right face
         position: [-0.707, 0.707, -1.414],
         tex_coords: [0.5, 0.],
+        normal: [-1., 0., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // H - bottom left - right face
         position: [-0.707, -0.707, -1.414],
         tex_coords: [0.5, 1.],
+        normal: [-1., 0., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // G - top left - top face
         position: [-0.707, 0.707, -1.414],
         tex_coords: [0.5, 0.],
+        normal: [0., 1., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // A - bottom left - top face
         position: [-0.707, 0.707, 0.],
         tex_coords: [0.5, 1.],
+        normal: [0., 1., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // D - bottom right - top face
         position: [0.707, 0.707, 0.],
         tex_coords: [1., 1.],
+        normal: [0., 1., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // E - top right - top face
         position: [0.707, 0.707, -1.414],
         tex_coords: [1., 0.],
+        normal: [0., 1., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // F - top left - bottom face
         position: [0.707, -0.707, -1.414],
         tex_coords: [0.5, 0.],
+        normal: [0., -1., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // C - bottom left - bottom face
         position: [0.707, -0.707, 0.],
         tex_coords: [0.5, 1.],
+        normal: [0., -1., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // B - bottom right - bottom face
         position: [-0.707, -0.707, 0.],
         tex_coords: [1., 1.],
+        normal: [0., -1., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
     Vertex {
         // H - top right - bottom face
         position: [-0.707, -0.707, -1.414],
         tex_coords: [1., 0.],
+        normal: [0., -1., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
     },
 ];
 const INDICES_CHAL: &[u16] = &[
@@ -207,12 +385,169 @@ const INDICES_CHAL: &[u16] = &[
     12, 13, 14, 12, 14, 15,
 ];
 
+const LIGHT_VERTICES: &[Vertex] = &[
+    Vertex {
+        position: [-0.1, -0.1, 0.1],
+        tex_coords: [0., 0.],
+        normal: [0., 0., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
+    },
+    Vertex {
+        position: [0.1, -0.1, 0.1],
+        tex_coords: [0., 0.],
+        normal: [0., 0., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
+    },
+    Vertex {
+        position: [0.1, 0.1, 0.1],
+        tex_coords: [0., 0.],
+        normal: [0., 0., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
+    },
+    Vertex {
+        position: [-0.1, 0.1, 0.1],
+        tex_coords: [0., 0.],
+        normal: [0., 0., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
+    },
+    Vertex {
+        position: [-0.1, -0.1, -0.1],
+        tex_coords: [0., 0.],
+        normal: [0., 0., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
+    },
+    Vertex {
+        position: [0.1, -0.1, -0.1],
+        tex_coords: [0., 0.],
+        normal: [0., 0., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
+    },
+    Vertex {
+        position: [0.1, 0.1, -0.1],
+        tex_coords: [0., 0.],
+        normal: [0., 0., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
+    },
+    Vertex {
+        position: [-0.1, 0.1, -0.1],
+        tex_coords: [0., 0.],
+        normal: [0., 0., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
+    },
+];
+const LIGHT_INDICES: &[u16] = &[
+    0, 1, 2, 0, 2, 3, 5, 4, 7, 5, 7, 6, 1, 5, 6, 1, 6, 2, 4, 0, 3, 4, 3, 7, 3, 2, 6, 3, 6, 7, 4, 5,
+    1, 4, 1, 0,
+];
+/// Units per second.
+const LIGHT_SPEED: f32 = 6.0;
+/// Radians per second.
+const SPIN_SPEED: f32 = 0.6;
+/// Used until a frame has been timed, and always on the web where there's no `Instant`.
+const DEFAULT_FRAME_DELTA: std::time::Duration = std::time::Duration::from_nanos(16_666_667);
+/// Longer frames (e.g. after a stall) are clamped so the camera doesn't jump.
+const MAX_FRAME_DELTA: std::time::Duration = std::time::Duration::from_millis(100);
+
+const GROUND_VERTICES: &[Vertex] = &[
+    Vertex {
+        position: [-20., -1., 20.],
+        tex_coords: [0., 1.],
+        normal: [0., 1., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
+    },
+    Vertex {
+        position: [20., -1., 20.],
+        tex_coords: [1., 1.],
+        normal: [0., 1., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
+    },
+    Vertex {
+        position: [20., -1., -20.],
+        tex_coords: [1., 0.],
+        normal: [0., 1., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
+    },
+    Vertex {
+        position: [-20., -1., -20.],
+        tex_coords: [0., 0.],
+        normal: [0., 1., 0.],
+        tangent: [0., 0., 0.],
+        bitangent: [0., 0., 0.],
+    },
+];
+const GROUND_INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
+
 const INSTANCES_PER_ROW: u32 = 10;
-const INSTANCE_DISPLACEMENT: cgmath::Vector3<f32> = cgmath::Vector3::new(
-    INSTANCES_PER_ROW as f32 * 0.5,
-    0.,
-    INSTANCES_PER_ROW as f32 * 0.5,
-);
+
+/// A spinning mesh with four children orbiting it, each with a smaller child of its own.
+/// Every node draws the first mesh.
+fn orbit_scene_graph() -> scene::SceneGraph {
+    let mut graph = scene::SceneGraph::new();
+    let root = graph.add_node(None, scene::Transform::default(), Some(0));
+    graph.set_spin(root, Some((cgmath::Vector3::unit_y(), cgmath::Rad(0.5))));
+    for i in 0..4 {
+        let angle = cgmath::Deg(90. * i as f32);
+        let child = graph.add_node(
+            Some(root),
+            scene::Transform {
+                translation: cgmath::Quaternion::from_angle_y(angle)
+                    .rotate_vector(cgmath::Vector3::new(3., 0., 0.)),
+                scale: cgmath::Vector3::new(0.5, 0.5, 0.5),
+                ..Default::default()
+            },
+            Some(0),
+        );
+        graph.set_spin(child, Some((cgmath::Vector3::unit_y(), cgmath::Rad(2.0))));
+        // In the child's (half-size) space, so one unit away in the world
+        graph.add_node(
+            Some(child),
+            scene::Transform {
+                translation: cgmath::Vector3::new(2., 0., 0.),
+                scale: cgmath::Vector3::new(0.5, 0.5, 0.5),
+                ..Default::default()
+            },
+            Some(0),
+        );
+    }
+    graph
+}
+
+/// A square grid of instances centred on the origin, each tilted away from the centre.
+fn instance_grid(per_row: u32) -> Vec<Instance> {
+    let displacement = cgmath::Vector3::new(per_row as f32 * 0.5, 0., per_row as f32 * 0.5);
+    (0..per_row)
+        .flat_map(|z| {
+            (0..per_row).map(move |x| {
+                let position = cgmath::Vector3 {
+                    x: x as f32,
+                    y: 0.0,
+                    z: z as f32,
+                } - displacement;
+                let rotation = if position.is_zero() {
+                    cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
+                } else {
+                    cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
+                };
+                Instance {
+                    position,
+                    rotation,
+                    scale: cgmath::Vector3::new(1., 1., 1.),
+                }
+            })
+        })
+        .collect()
+}
 
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.5, 0.0, 0.0, 0.0, 0.5, 1.0,
@@ -223,184 +558,400 @@ struct Camera {
     target: cgmath::Point3<f32>,
     up: cgmath::Vector3<f32>,
     aspect: f32,
+    projection: camera::Projection,
+    /// Vertical field of view in degrees, for perspective projection.
     fovy: f32,
     znear: f32,
     zfar: f32,
 }
 impl Camera {
     fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
-        return proj * view;
+        self.build_projection_matrix() * self.build_view_matrix()
+    }
+    fn build_view_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+    fn build_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        match self.projection {
+            camera::Projection::Perspective => {
+                cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar)
+            }
+            camera::Projection::Orthographic { half_height } => {
+                let half_width = half_height * self.aspect;
+                cgmath::ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.znear,
+                    self.zfar,
+                )
+            }
+        }
+    }
+    /// Maps clip space back to world-space directions from the eye, for the skybox. Only the
+    /// view's rotation is kept, so the sky doesn't move when the camera does.
+    fn build_sky_inverse_matrix(&self) -> cgmath::Matrix4<f32> {
+        let mut rotation = self.build_view_matrix();
+        rotation.w = cgmath::Vector4::unit_w();
+        (OPENGL_TO_WGPU_MATRIX * self.build_projection_matrix() * rotation)
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity)
     }
 }
 
 struct CameraStaging {
     camera: Camera,
-    rotation: cgmath::Deg<f32>,
 }
 impl CameraStaging {
     fn new(camera: Camera) -> Self {
-        Self {
-            camera,
-            rotation: cgmath::Deg(0.0),
-        }
+        Self { camera }
     }
     fn update_camera(&self, camera_uniform: &mut CameraUniform) {
-        camera_uniform.view_proj = (OPENGL_TO_WGPU_MATRIX
-            * self.camera.build_view_projection_matrix()
-            * cgmath::Matrix4::from_angle_z(self.rotation))
-        .into();
+        camera_uniform.view_position = self.camera.eye.to_homogeneous().into();
+        camera_uniform.view_proj =
+            (OPENGL_TO_WGPU_MATRIX * self.camera.build_view_projection_matrix()).into();
+        camera_uniform.sky_inv_view_proj = self.camera.build_sky_inverse_matrix().into();
     }
 }
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct CameraUniform {
+    view_position: [f32; 4],
     view_proj: [[f32; 4]; 4],
+    sky_inv_view_proj: [[f32; 4]; 4],
 }
 impl CameraUniform {
     fn new() -> Self {
         Self {
+            view_position: [0.0; 4],
             view_proj: cgmath::Matrix4::identity().into(),
+            sky_inv_view_proj: cgmath::Matrix4::identity().into(),
         }
     }
 }
 
-struct CameraController {
-    speed: f32,
-    forward_down: bool,
-    backward_down: bool,
-    left_down: bool,
-    right_down: bool,
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightUniform {
+    position: [f32; 3],
+    // Fills the padding after `position` (vec3 is 16 byte aligned in WGSL)
+    intensity: f32,
+    color: [f32; 3],
+    _padding: u32,
 }
-impl CameraController {
-    fn new(speed: f32) -> Self {
-        Self {
-            speed,
-            forward_down: false,
-            backward_down: false,
-            left_down: false,
-            right_down: false,
-        }
-    }
-    fn process_events(&mut self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::KeyboardInput {
-                input:
-                    KeyboardInput {
-                        state,
-                        virtual_keycode: Some(keycode),
-                        ..
-                    },
-                ..
-            } => {
-                let pressed = *state == ElementState::Pressed;
-                match keycode {
-                    VirtualKeyCode::W | VirtualKeyCode::Up => {
-                        self.forward_down = pressed;
-                        true
-                    }
-                    VirtualKeyCode::S | VirtualKeyCode::Down => {
-                        self.backward_down = pressed;
-                        true
-                    }
-                    VirtualKeyCode::A | VirtualKeyCode::Left => {
-                        self.left_down = pressed;
-                        true
-                    }
-                    VirtualKeyCode::D | VirtualKeyCode::Right => {
-                        self.right_down = pressed;
-                        true
-                    }
-                    _ => false,
-                }
-            }
-            _ => false,
-        }
+
+/// The MSAA sample counts the adapter can render `format` with (always including 1).
+///
+/// wgpu only reports whether a format can be multisampled at all, and like WebGPU its render
+/// passes only accept 4 samples, so 2x and 8x are never supported for now.
+fn supported_sample_counts(adapter: &wgpu::Adapter, format: wgpu::TextureFormat) -> Vec<u32> {
+    let color = adapter.get_texture_format_features(format).flags;
+    let depth = adapter
+        .get_texture_format_features(texture::Texture::DEPTH_FORMAT)
+        .flags;
+    let multisample = color.contains(
+        wgpu::TextureFormatFeatureFlags::MULTISAMPLE
+            | wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE,
+    ) && depth.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE);
+    if multisample {
+        vec![1, 4]
+    } else {
+        vec![1]
     }
-    fn update_camera(&self, camera: &mut Camera) {
-        let forward = camera.target - camera.eye;
-        let forward_norm = forward.normalize();
-        let forward_mag = forward.magnitude();
+}
 
-        if self.forward_down && forward_mag > self.speed {
-            camera.eye += forward_norm * self.speed;
-        }
-        if self.backward_down {
-            camera.eye -= forward_norm * self.speed;
-        }
+enum RenderTarget {
+    Surface(wgpu::Surface),
+    Offscreen(texture::Texture),
+}
 
-        let right = forward_norm.cross(camera.up);
+/// The HDR textures the render graph allocates, bound again whenever it reallocates them.
+struct HdrTextures {
+    /// The scene, or the scene with bloom added while bloom is on.
+    tonemap_input: render_graph::ResourceId,
+    post: Option<post::PostTextures>,
+}
 
-        let forward = camera.target - camera.eye;
-        let forward_mag = forward.magnitude();
+/// The options the adapter was found with, to find another one after the device is lost.
+#[derive(Clone, Copy, Debug)]
+struct AdapterRequest {
+    backends: wgpu::Backends,
+    power_preference: wgpu::PowerPreference,
+    force_fallback_adapter: bool,
+}
 
-        if self.right_down {
-            camera.eye = camera.target - (forward + right * self.speed).normalize() * forward_mag;
-        }
-        if self.left_down {
-            camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
-        }
-    }
+/// The file `State::model` was loaded from, to load it again on a new device.
+#[derive(Clone, Debug)]
+enum ModelSource {
+    Obj(std::path::PathBuf),
+    Gltf(std::path::PathBuf),
+    Scene(std::path::PathBuf),
 }
 
-struct State {
-    surface: wgpu::Surface,
+pub struct State {
+    /// Shared with the `State` built on a new device when this one is lost.
+    instance: Arc<wgpu::Instance>,
+    adapter_request: AdapterRequest,
+    target: RenderTarget,
     device: wgpu::Device,
     queue: wgpu::Queue,
+    /// Set when the device's errors, or a frame's panic, report the device as lost.
+    device_lost: device_lost::DeviceLost,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
 
     camera_staging: CameraStaging,
-    camera_controller: CameraController,
+    camera_controller: camera::OrbitCameraController,
+    fly_camera_controller: camera::FlyCameraController,
+    /// Whether the first-person fly camera is active instead of the orbiting one.
+    fly_mode: bool,
+    /// The projection the P key switches to.
+    alternate_projection: camera::Projection,
     camera_uniform: CameraUniform,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
 
+    light_uniform: LightUniform,
+    light_buffer: wgpu::Buffer,
+    light_bind_group: wgpu::BindGroup,
+    light_render_pipeline: pipeline::PipelineId,
+    light_vertex_buffer: wgpu::Buffer,
+    light_index_buffer: wgpu::Buffer,
+    light_movement: cgmath::Vector3<f32>,
+
+    shadow_map: shadow::ShadowMap,
+    ground_vertex_buffer: wgpu::Buffer,
+    ground_index_buffer: wgpu::Buffer,
+    ground_instance_buffer: wgpu::Buffer,
+    ground_material: model::Material,
+
+    /// The background where the skybox isn't drawn.
     clear_color: wgpu::Color,
-
-    depth_texture: texture::Texture,
-    render_pipeline: wgpu::RenderPipeline,
-    render_pipeline_chal: wgpu::RenderPipeline,
+    /// Starts with the built-in sky, in an HDR cubemap if the adapter can render one.
+    skybox: skybox::Skybox,
+    skybox_source: Option<skybox::SkyboxSource>,
+    show_skybox: bool,
+
+    /// MSAA samples per pixel, 1 when off.
+    sample_count: u32,
+    /// The count last asked for, which switching HDR on or off picks `sample_count` from.
+    requested_sample_count: u32,
+    /// The counts the adapter supports for the surface format, see `supported_sample_counts`.
+    surface_sample_counts: Vec<u32>,
+    /// The same for `hdr::HDR_FORMAT`.
+    hdr_sample_counts: Vec<u32>,
+    /// The HDR scene target and tonemapping, if the adapter can render to `hdr::HDR_FORMAT`.
+    hdr_target: Option<hdr::HdrTarget>,
+    use_hdr: bool,
+    /// Bloom, color grading, vignette and FXAA, which run along with the HDR target.
+    post: Option<post::PostProcess>,
+    /// The HDR textures in `render_graph`, while HDR is on.
+    hdr_textures: Option<HdrTextures>,
+    /// Where the color-grading LUT was loaded from, if not the built-in one.
+    color_lut_path: Option<std::path::PathBuf>,
+    pipelines: pipeline::PipelineCache,
+    /// The frame's passes, rebuilt when the sample count, HDR setting or bloom changes.
+    render_graph: render_graph::RenderGraph<State>,
+    render_pipeline: pipeline::PipelineId,
+    render_pipeline_chal: pipeline::PipelineId,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     num_indices: u32,
+    pentagon_bounds: culling::BoundingSphere,
 
     vertex_buffer_chal: wgpu::Buffer,
     index_buffer_chal: wgpu::Buffer,
     num_indices_chal: u32,
 
     instances: Vec<Instance>,
+    /// Every instance, for the shadow pass (off-screen instances can still cast shadows).
     instance_buffer: wgpu::Buffer,
+    /// The instances that passed frustum culling this frame, grouped by mesh.
+    visible_instance_buffer: wgpu::Buffer,
+    /// How many instances `visible_instance_buffer` has room for.
+    visible_instance_capacity: usize,
+    /// Range of `visible_instance_buffer` per mesh of `model`, or just the pentagon's.
+    visible_instances: Vec<std::ops::Range<u32>>,
+    frustum_culling: bool,
+    culling_stats: culling::CullingStats,
+    /// Whether the last frame culled on the GPU, so the stats are read back from it instead.
+    gpu_culling_stats: bool,
+    /// The compute path for animating and culling instances, if the adapter supports it.
+    gpu_instances: Option<instance_compute::GpuInstances>,
+    use_gpu_instances: bool,
+
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    model: Option<model::Model>,
+    model_source: Option<ModelSource>,
+    /// Instance range per mesh of `model`, empty to draw every mesh with all instances.
+    mesh_instances: Vec<std::ops::Range<u32>>,
+    spin_instances: bool,
+    /// Where `instances` come from when set, instead of the grid or a glTF scene.
+    scene_graph: Option<scene::SceneGraph>,
 
     diffuse_bind_group: wgpu::BindGroup,
+    #[allow(dead_code)]
     diffuse_texture: texture::Texture,
 
     diffuse_bind_group_chal: wgpu::BindGroup,
+    #[allow(dead_code)]
     diffuse_texture_chal: texture::Texture,
 
     space_down: bool,
 
     last_frame: Option<std::time::Instant>,
+    /// Time between the last two frames, which all movement is scaled by.
+    frame_delta: std::time::Duration,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    shader_watcher: Option<hot_reload::ShaderWatcher>,
 }
 impl State {
-    async fn new(window: &Window) -> Self {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    async fn new(window: &Window, options: &config::Config) -> Result<Self, InitError> {
         let size = window.inner_size();
 
-        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let request = AdapterRequest {
+            backends: options.backend.into(),
+            power_preference: options.power_preference.into(),
+            force_fallback_adapter: options.force_fallback_adapter,
+        };
+        let instance = wgpu::Instance::new(request.backends);
         let surface = unsafe { instance.create_surface(window) };
         // NOTE: could be none, see: https://sotrh.github.io/learn-wgpu/beginner/tutorial2-surface/#state-new
-        let adapter = instance
+        let adapter = Self::request_adapter(&instance, request, Some(&surface)).await?;
+        log::info!("Adapter: {:?}", adapter.get_info());
+
+        let formats = surface.get_supported_formats(&adapter);
+        if formats.is_empty() {
+            return Err(InitError::UnsupportedSurface {
+                adapter: adapter.get_info().name,
+            });
+        }
+        let (device, queue) = Self::request_device(&adapter).await?;
+
+        let format = match options.surface_format.map(wgpu::TextureFormat::from) {
+            Some(format) if formats.contains(&format) => format,
+            Some(format) => {
+                log::warn!(
+                    "Surface doesn't support {:?}, using {:?}",
+                    format,
+                    formats[0]
+                );
+                formats[0]
+            }
+            None => formats[0],
+        };
+        let present_mode = wgpu::PresentMode::from(options.present_mode);
+        let present_mode = if surface
+            .get_supported_modes(&adapter)
+            .contains(&present_mode)
+        {
+            present_mode
+        } else {
+            log::warn!("Surface doesn't support {:?}, using Fifo", present_mode);
+            wgpu::PresentMode::Fifo
+        };
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode,
+        };
+        surface.configure(&device, &config);
+
+        let mut state = Self::from_device(
+            Arc::new(instance),
+            request,
+            &adapter,
+            device,
+            queue,
+            config,
+            RenderTarget::Surface(surface),
+        )
+        .await?;
+        state.set_msaa_samples(options.msaa_samples.unwrap_or(1));
+        Ok(state)
+    }
+    /// Builds a `State` without a window that draws into an offscreen texture.
+    /// Prefers the fallback (software) adapter so it also works on machines without a GPU.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn new_headless(width: u32, height: u32) -> Result<Self, InitError> {
+        Self::new_headless_with_format(width, height, Self::HEADLESS_FORMAT).await
+    }
+    /// Like [`Self::new_headless`], but drawing into `format`, which has to be `Rgba8Unorm`
+    /// or `Rgba8UnormSrgb` for [`Self::capture_frame`] to read it back.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn new_headless_with_format(
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self, InitError> {
+        let mut request = AdapterRequest {
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: true,
+        };
+        let instance = wgpu::Instance::new(request.backends);
+        let adapter = match Self::request_adapter(&instance, request, None).await {
+            Ok(adapter) => adapter,
+            Err(_) => {
+                log::warn!("No fallback adapter found, using any available adapter");
+                request.force_fallback_adapter = false;
+                Self::request_adapter(&instance, request, None).await?
+            }
+        };
+        log::info!("Headless adapter: {:?}", adapter.get_info());
+
+        let (device, queue) = Self::request_device(&adapter).await?;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        let render_target =
+            texture::Texture::create_render_target(&device, &config, "render_target");
+
+        Self::from_device(
+            Arc::new(instance),
+            request,
+            &adapter,
+            device,
+            queue,
+            config,
+            RenderTarget::Offscreen(render_target),
+        )
+        .await
+    }
+    async fn request_adapter(
+        instance: &wgpu::Instance,
+        request: AdapterRequest,
+        compatible_surface: Option<&wgpu::Surface>,
+    ) -> Result<wgpu::Adapter, InitError> {
+        instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
+                power_preference: request.power_preference,
+                compatible_surface,
+                force_fallback_adapter: request.force_fallback_adapter,
             })
             .await
-            .unwrap();
-
-        let (device, queue) = adapter
+            .ok_or(InitError::NoAdapter {
+                backends: request.backends,
+                force_fallback_adapter: request.force_fallback_adapter,
+            })
+    }
+    async fn request_device(
+        adapter: &wgpu::Adapter,
+    ) -> Result<(wgpu::Device, wgpu::Queue), InitError> {
+        adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     features: wgpu::Features::empty(),
@@ -414,20 +965,40 @@ impl State {
                 None,
             )
             .await
-            .unwrap();
-
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_supported_formats(&adapter)[0],
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
-        };
-        surface.configure(&device, &config);
+            .map_err(|source| InitError::RequestDevice {
+                adapter: adapter.get_info().name,
+                source,
+            })
+    }
+    /// Creates everything the scene needs on `device`. Shader and pipeline validation errors
+    /// are returned instead of panicking.
+    async fn from_device(
+        instance: Arc<wgpu::Instance>,
+        adapter_request: AdapterRequest,
+        adapter: &wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        target: RenderTarget,
+    ) -> Result<Self, InitError> {
+        let size = winit::dpi::PhysicalSize::new(config.width, config.height);
+
+        let device_lost = device_lost::DeviceLost::watch(&device);
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
 
         let diffuse_bytes = include_bytes!("happy-tree.png");
-        let diffuse_texture =
-            texture::Texture::from_bytes(&device, &queue, diffuse_bytes, "happy-tree.png").unwrap();
+        let diffuse_texture = texture::Texture::from_bytes_with_options(
+            &device,
+            &queue,
+            diffuse_bytes,
+            "happy-tree.png",
+            texture::TextureOptions {
+                generate_mipmaps: true,
+                filtering: texture::Filtering::Anisotropic(16),
+                ..Default::default()
+            },
+        )
+        .map_err(|err| InitError::texture("happy-tree.png", err))?;
 
         let diffuse_bytes_chal = include_bytes!("minecraft-grass.png");
         let diffuse_texture_chal = texture::Texture::from_bytes(
@@ -436,7 +1007,11 @@ impl State {
             diffuse_bytes_chal,
             "minecraft-grass.png",
         )
-        .unwrap();
+        .map_err(|err| InitError::texture("minecraft-grass.png", err))?;
+
+        // Shared by the built-in meshes, which have no normal maps
+        let flat_normal_texture = model::Material::flat_normal_texture(&device, &queue)
+            .map_err(|err| InitError::texture("flat_normal", err))?;
 
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -457,6 +1032,22 @@ impl State {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
                 ],
                 label: Some("texture_bind_group_layout"),
             });
@@ -472,6 +1063,14 @@ impl State {
                     binding: 1,
                     resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&flat_normal_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&flat_normal_texture.sampler),
+                },
             ],
             label: Some("diffuse_bind_group"),
         });
@@ -487,6 +1086,14 @@ impl State {
                     binding: 1,
                     resource: wgpu::BindingResource::Sampler(&diffuse_texture_chal.sampler),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&flat_normal_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&flat_normal_texture.sampler),
+                },
             ],
             label: Some("diffuse_bind_group_chal"),
         });
@@ -496,11 +1103,13 @@ impl State {
             target: (0., 0., 0.).into(),
             up: cgmath::Vector3::unit_y(),
             aspect: size.width as f32 / size.height as f32,
+            projection: camera::Projection::Perspective,
             fovy: 45.,
             znear: 0.1,
             zfar: 100.,
         };
-        let camera_controller = CameraController::new(0.2);
+        let camera_controller = camera::OrbitCameraController::new(0.01);
+        let fly_camera_controller = camera::FlyCameraController::new(4., 0.003);
 
         let mut camera_uniform = CameraUniform::new();
         let camera_staging = CameraStaging::new(camera);
@@ -516,7 +1125,7 @@ impl State {
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -535,101 +1144,170 @@ impl State {
             label: Some("camera_bind_group"),
         });
 
-        let depth_texture = texture::Texture::create_depth_texture(&device, &config, "depth_texture");
-
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        let light_uniform = LightUniform {
+            position: [2.0, 2.0, -1.0],
+            intensity: 1.0,
+            color: [1.0, 1.0, 1.0],
+            _padding: 0,
+        };
+        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Buffer"),
+            contents: bytemuck::cast_slice(&[light_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let light_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("light_bind_group_layout"),
+            });
+        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &light_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: light_buffer.as_entire_binding(),
+            }],
+            label: Some("light_bind_group"),
         });
-        let render_pipeline_layout =
+
+        let surface_sample_counts = supported_sample_counts(adapter, config.format);
+        let hdr_sample_counts = supported_sample_counts(adapter, hdr::HDR_FORMAT);
+
+        let mut shadow_map = shadow::ShadowMap::new(&device, shadow::ShadowSettings::default());
+        shadow_map.update(&queue, light_uniform.position.into());
+
+        let mut pipelines = pipeline::PipelineCache::new();
+        let hdr_target = if hdr::HdrTarget::is_supported(adapter) {
+            Some(hdr::HdrTarget::new(
+                &device,
+                &config,
+                &mut pipelines,
+                hdr::TonemapSettings::default(),
+            ))
+        } else {
+            log::warn!("Can't render to {:?}, drawing without HDR", hdr::HDR_FORMAT);
+            None
+        };
+        let post = match &hdr_target {
+            Some(_) => {
+                let lut = texture::Texture::lut_from_bytes(
+                    &device,
+                    &queue,
+                    include_bytes!("../res/luts/warm.png"),
+                    "warm.png",
+                )
+                .map_err(|err| InitError::texture("warm.png", err))?;
+                Some(post::PostProcess::new(
+                    &device,
+                    &config,
+                    &mut pipelines,
+                    lut,
+                    post::PostSettings::default(),
+                ))
+            }
+            None => None,
+        };
+        let scene_format = if hdr_target.is_some() {
+            hdr::HDR_FORMAT
+        } else {
+            config.format
+        };
+        // The built-in sky keeps its range in an HDR cubemap, which needs the same support
+        let sky_format = skybox::equirect_format(hdr_target.is_some());
+        let cubemap = texture::Texture::cubemap_from_equirectangular(
+            &device,
+            &queue,
+            include_bytes!("../res/skybox/sky.hdr"),
+            skybox::EQUIRECT_FACE_SIZE,
+            sky_format,
+            "sky.hdr",
+        )
+        .map_err(|err| InitError::texture("sky.hdr", err))?;
+        let skybox = skybox::Skybox::new(
+            &device,
+            &mut pipelines,
+            &camera_bind_group_layout,
+            cubemap,
+            scene_format,
+            1,
+        );
+        pipelines.add_layout(
+            "main",
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &camera_bind_group_layout,
+                    &light_bind_group_layout,
+                    &shadow_map.bind_group_layout,
+                ],
                 push_constant_ranges: &[],
-            });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc(), InstanceRaw::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
             }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu:: StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
+        );
+        pipelines.add_layout(
+            "light",
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Light Pipeline Layout"),
+                bind_group_layouts: &[&camera_bind_group_layout, &light_bind_group_layout],
+                push_constant_ranges: &[],
             }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
+        );
+        let render_pipeline = pipelines.get_or_create(
+            &device,
+            &pipeline::PipelineDesc::new(
+                "Render Pipeline",
+                include_str!("shader.wgsl"),
+                "main",
+                scene_format,
+            )
+            .vertex_layouts(&[Vertex::desc(), InstanceRaw::desc()])
+            .depth(pipeline::DepthState::default()),
+        );
+        let render_pipeline_chal = pipelines.get_or_create(
+            &device,
+            &pipeline::PipelineDesc::new(
+                "Challenge Render Pipeline",
+                include_str!("challenge.wgsl"),
+                "main",
+                scene_format,
+            )
+            .vertex_layouts(&[Vertex::desc()])
+            .depth(pipeline::DepthState::default()),
+        );
+        let light_render_pipeline = pipelines.get_or_create(
+            &device,
+            &pipeline::PipelineDesc::new(
+                "Light Render Pipeline",
+                include_str!("light.wgsl"),
+                "light",
+                scene_format,
+            )
+            .vertex_layouts(&[Vertex::desc()])
+            .cull_mode(None)
+            .depth(pipeline::DepthState::default()),
+        );
+        let light_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Vertex Buffer"),
+            contents: bytemuck::cast_slice(LIGHT_VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
         });
-
-        let shader_chal = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Challenge Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("challenge.wgsl").into()),
-        });
-        let render_pipeline_chal = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Challenge Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader_chal,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_chal,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
+        let light_index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Index Buffer"),
+            contents: bytemuck::cast_slice(LIGHT_INDICES),
+            usage: wgpu::BufferUsages::INDEX,
         });
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(VERTICES),
+            contents: bytemuck::cast_slice(&Vertex::with_tangents(VERTICES, INDICES)),
             usage: wgpu::BufferUsages::VERTEX,
         });
         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -638,10 +1316,11 @@ impl State {
             usage: wgpu::BufferUsages::INDEX,
         });
         let num_indices = INDICES.len() as u32;
+        let pentagon_bounds = culling::BoundingSphere::from_vertices(VERTICES);
 
         let vertex_buffer_chal = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Challenge Vertex Buffer"),
-            contents: bytemuck::cast_slice(VERTICES_CHAL),
+            contents: bytemuck::cast_slice(&Vertex::with_tangents(VERTICES_CHAL, INDICES_CHAL)),
             usage: wgpu::BufferUsages::VERTEX,
         });
         let index_buffer_chal = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -651,33 +1330,52 @@ impl State {
         });
         let num_indices_chal = INDICES_CHAL.len() as u32;
 
-        let instances = (0..INSTANCES_PER_ROW)	
-            .flat_map(|z| {	
-                (0..INSTANCES_PER_ROW).map(move |x| {	
-                    let position = cgmath::Vector3 {	
-                        x: x as f32,	
-                        y: 0.0,	
-                        z: z as f32,	
-                    } - INSTANCE_DISPLACEMENT;	
-                    let rotation = if position.is_zero() {	
-                        cgmath::Quaternion::from_axis_angle(	
-                            cgmath::Vector3::unit_z(),	
-                            cgmath::Deg(0.0),	
-                        )	
-                    } else {	
-                        cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))	
-                    };
-                    Instance { position, rotation }	
-                })	
-            })	
-            .collect::<Vec<_>>();	
-        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();	
-        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {	
-            label: Some("Instance Buffer"),	
-            contents: bytemuck::cast_slice(&instance_data),	
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-        });	
+        let ground_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Ground Vertex Buffer"),
+            contents: bytemuck::cast_slice(&Vertex::with_tangents(GROUND_VERTICES, GROUND_INDICES)),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let ground_index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Ground Index Buffer"),
+            contents: bytemuck::cast_slice(GROUND_INDICES),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let ground_instance = Instance {
+            position: cgmath::Vector3::zero(),
+            rotation: cgmath::Quaternion::one(),
+            scale: cgmath::Vector3::new(1., 1., 1.),
+        };
+        let ground_instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Ground Instance Buffer"),
+            contents: bytemuck::cast_slice(&[ground_instance.to_raw()]),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let ground_material = model::Material::new(
+            &device,
+            "ground",
+            model::Material::solid_color_texture(&device, &queue, [180, 180, 180, 255])
+                .map_err(|err| InitError::texture("solid_color", err))?,
+            model::Material::flat_normal_texture(&device, &queue)
+                .map_err(|err| InitError::texture("flat_normal", err))?,
+            &texture_bind_group_layout,
+        );
 
+        let instances = instance_grid(INSTANCES_PER_ROW);
+        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Instance Buffer"),
+            contents: bytemuck::cast_slice(&instance_data),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        let visible_instance_capacity = instances.len();
+        let gpu_instances = if instance_compute::GpuInstances::is_supported(adapter) {
+            Some(instance_compute::GpuInstances::new(&device))
+        } else {
+            log::info!("No compute shaders, animating and culling instances on the CPU");
+            None
+        };
+        let visible_instance_buffer =
+            Self::create_visible_instance_buffer(&device, visible_instance_capacity);
 
         let clear_color = wgpu::Color {
             r: 0.1,
@@ -686,52 +1384,119 @@ impl State {
             a: 1.0,
         };
         let space_down = false;
-        let mut last_frame = None;
-        cfg_if::cfg_if! {
-            if #[cfg(target_arch = "wasm32")] {}
-            else {
-                last_frame = Some(std::time::Instant::now());
-            }
+        let last_frame = if cfg!(target_arch = "wasm32") {
+            None
+        } else {
+            Some(std::time::Instant::now())
         };
 
-        Self {
-            surface,
+        if let Some(error) = device.pop_error_scope().await {
+            return Err(InitError::Shader(error.to_string()));
+        }
+
+        let mut state = Self {
+            instance,
+            adapter_request,
+            target,
             device,
             queue,
+            device_lost,
             config,
             size,
             camera_staging,
             camera_controller,
+            fly_camera_controller,
+            fly_mode: false,
+            alternate_projection: camera::Projection::Orthographic { half_height: 2. },
             camera_uniform,
             camera_buffer,
             camera_bind_group,
+            light_uniform,
+            light_buffer,
+            light_bind_group,
+            light_render_pipeline,
+            light_vertex_buffer,
+            light_index_buffer,
+            light_movement: cgmath::Vector3::zero(),
+            shadow_map,
+            ground_vertex_buffer,
+            ground_index_buffer,
+            ground_instance_buffer,
+            ground_material,
             clear_color,
-            depth_texture,
+            skybox_source: Some(skybox::SkyboxSource::BuiltIn),
+            skybox,
+            show_skybox: true,
+            sample_count: 1,
+            requested_sample_count: 1,
+            surface_sample_counts,
+            hdr_sample_counts,
+            use_hdr: hdr_target.is_some(),
+            hdr_target,
+            post,
+            hdr_textures: None,
+            color_lut_path: None,
+            pipelines,
+            render_graph: render_graph::RenderGraph::new(),
             render_pipeline,
             render_pipeline_chal,
             vertex_buffer,
             index_buffer,
             num_indices,
+            pentagon_bounds,
             vertex_buffer_chal,
             index_buffer_chal,
             num_indices_chal,
             instances,
             instance_buffer,
+            visible_instance_buffer,
+            visible_instance_capacity,
+            visible_instances: Vec::new(),
+            frustum_culling: true,
+            culling_stats: culling::CullingStats::default(),
+            gpu_culling_stats: false,
+            use_gpu_instances: gpu_instances.is_some(),
+            gpu_instances,
+            texture_bind_group_layout,
+            model: None,
+            model_source: None,
+            mesh_instances: Vec::new(),
+            spin_instances: true,
+            scene_graph: None,
             diffuse_bind_group,
             diffuse_texture,
             diffuse_bind_group_chal,
             diffuse_texture_chal,
             space_down,
             last_frame,
-        }
+            frame_delta: DEFAULT_FRAME_DELTA,
+            #[cfg(not(target_arch = "wasm32"))]
+            shader_watcher: None,
+        };
+        state.build_render_graph().map_err(InitError::RenderGraph)?;
+        state.upload_gpu_instances();
+        Ok(state)
     }
-    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
-            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+            self.camera_staging.camera.aspect = new_size.width as f32 / new_size.height as f32;
+            self.write_camera();
+            match &mut self.target {
+                RenderTarget::Surface(surface) => surface.configure(&self.device, &self.config),
+                RenderTarget::Offscreen(render_target) => {
+                    *render_target = texture::Texture::create_render_target(
+                        &self.device,
+                        &self.config,
+                        "render_target",
+                    );
+                }
+            }
+            self.render_graph
+                .resize(&self.device, new_size.width, new_size.height);
+            self.bind_graph_textures();
         }
     }
     fn input(&mut self, event: &WindowEvent) -> bool {
@@ -748,31 +1513,218 @@ impl State {
                 input:
                     KeyboardInput {
                         state,
-                        virtual_keycode: Some(VirtualKeyCode::Space),
+                        virtual_keycode: Some(keycode),
                         ..
                     },
                 ..
-            } => self.space_down = *state == ElementState::Pressed,
+            } => {
+                let pressed = *state == ElementState::Pressed;
+                let amount = if pressed { 1. } else { 0. };
+                match keycode {
+                    VirtualKeyCode::Space => self.space_down = pressed,
+                    VirtualKeyCode::P if pressed => {
+                        std::mem::swap(
+                            &mut self.camera_staging.camera.projection,
+                            &mut self.alternate_projection,
+                        );
+                        self.write_camera();
+                    }
+                    VirtualKeyCode::F if pressed => {
+                        self.fly_mode = !self.fly_mode;
+                        if self.fly_mode {
+                            self.fly_camera_controller
+                                .look_along(&self.camera_staging.camera);
+                        }
+                        return true;
+                    }
+                    VirtualKeyCode::G if pressed => {
+                        let enabled = self.set_gpu_instances(!self.use_gpu_instances);
+                        log::info!("Instances on the {}", if enabled { "GPU" } else { "CPU" });
+                    }
+                    VirtualKeyCode::H if pressed => {
+                        if self.scene_graph.is_some() {
+                            self.set_instance_grid(INSTANCES_PER_ROW);
+                            self.spin_instances = true;
+                        } else {
+                            self.set_scene_graph(orbit_scene_graph());
+                        }
+                    }
+                    VirtualKeyCode::Y if pressed => {
+                        self.set_skybox_visible(!self.show_skybox);
+                    }
+                    VirtualKeyCode::M if pressed => {
+                        let next = self
+                            .supported_sample_counts()
+                            .iter()
+                            .copied()
+                            .find(|&count| count > self.sample_count)
+                            .unwrap_or(1);
+                        self.set_msaa_samples(next);
+                        log::info!("MSAA: {}x", self.sample_count);
+                    }
+                    VirtualKeyCode::T if pressed => {
+                        let mut settings = self.tonemap_settings();
+                        settings.tonemapping = match settings.tonemapping {
+                            hdr::Tonemapping::Aces => hdr::Tonemapping::Reinhard,
+                            hdr::Tonemapping::Reinhard => hdr::Tonemapping::Clamp,
+                            hdr::Tonemapping::Clamp => hdr::Tonemapping::Aces,
+                        };
+                        self.set_tonemap_settings(settings);
+                        log::info!("Tonemapping: {:?}", settings.tonemapping);
+                    }
+                    VirtualKeyCode::Minus | VirtualKeyCode::Equals if pressed => {
+                        // Half a stop per press
+                        let step = if *keycode == VirtualKeyCode::Minus {
+                            -0.5
+                        } else {
+                            0.5
+                        };
+                        let mut settings = self.tonemap_settings();
+                        settings.exposure *= 2f32.powf(step);
+                        self.set_tonemap_settings(settings);
+                        log::info!("Exposure: {:.2}", settings.exposure);
+                    }
+                    VirtualKeyCode::B
+                    | VirtualKeyCode::C
+                    | VirtualKeyCode::V
+                    | VirtualKeyCode::X
+                        if pressed =>
+                    {
+                        let mut settings = self.post_settings();
+                        let (name, enabled) = match keycode {
+                            VirtualKeyCode::B => ("Bloom", &mut settings.bloom.enabled),
+                            VirtualKeyCode::C => {
+                                ("Color grading", &mut settings.color_grading.enabled)
+                            }
+                            VirtualKeyCode::V => ("Vignette", &mut settings.vignette.enabled),
+                            _ => ("FXAA", &mut settings.fxaa.enabled),
+                        };
+                        *enabled = !*enabled;
+                        log::info!("{}: {}", name, if *enabled { "on" } else { "off" });
+                        self.set_post_settings(settings);
+                    }
+                    VirtualKeyCode::J => self.light_movement.x = -amount,
+                    VirtualKeyCode::L => self.light_movement.x = amount,
+                    VirtualKeyCode::O => self.light_movement.y = -amount,
+                    VirtualKeyCode::U => self.light_movement.y = amount,
+                    VirtualKeyCode::I => self.light_movement.z = -amount,
+                    VirtualKeyCode::K => self.light_movement.z = amount,
+                    _ => {}
+                }
+            }
             _ => {}
         }
-        self.camera_controller.process_events(event)
+        if self.fly_mode {
+            self.fly_camera_controller.process_events(event)
+        } else {
+            self.camera_controller.process_events(event)
+        }
     }
-    fn update(&mut self) {
+    fn device_input(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta: (dx, dy) } = event {
+            if self.fly_mode {
+                self.fly_camera_controller.process_mouse_motion(*dx, *dy);
+            }
+        }
+    }
+    /// The fly camera grabs and hides the cursor so the mouse can turn freely.
+    fn wants_cursor_grab(&self) -> bool {
+        self.fly_mode
+    }
+    /// Advances the animation, camera and light by the last frame's duration. If the device
+    /// turns out to be lost, the rest of the update is skipped, see [`Self::is_device_lost`].
+    pub fn update(&mut self) {
+        let device_lost = self.device_lost.clone();
+        device_lost.catch(|| self.update_frame());
+    }
+    fn update_frame(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(watcher) = &mut self.shader_watcher {
+            watcher.poll(&self.device, &mut self.pipelines);
+        }
 
-        for instance in &mut self.instances {
-            instance.spin();
+        let dt = self.frame_delta.as_secs_f32();
+        if let Some(graph) = &mut self.scene_graph {
+            graph.animate(dt);
         }
+        self.sync_scene_graph(false);
+
+        let spin = cgmath::Rad(SPIN_SPEED * dt);
+        let gpu_instances = self
+            .gpu_instances
+            .as_mut()
+            .filter(|_| self.use_gpu_instances);
+        if let Some(gpu_instances) = gpu_instances {
+            if self.spin_instances {
+                gpu_instances.spin(spin);
+            }
+        } else {
+            if self.spin_instances {
+                for instance in &mut self.instances {
+                    instance.spin(spin);
+                }
+            }
 
-        let instance_data = self.instances.iter().map(Instance::to_raw).collect::<Vec<_>>();	
+            let instance_data = self
+                .instances
+                .iter()
+                .map(Instance::to_raw)
+                .collect::<Vec<_>>();
+            self.queue.write_buffer(
+                &self.instance_buffer,
+                0,
+                bytemuck::cast_slice(&instance_data),
+            );
+        }
+
+        if self.fly_mode {
+            self.fly_camera_controller
+                .update_camera(&mut self.camera_staging.camera, dt);
+        } else {
+            self.camera_controller
+                .update_camera(&mut self.camera_staging.camera, dt);
+        }
+        self.write_camera();
+
+        let light_position = cgmath::Vector3::from(self.light_uniform.position)
+            + self.light_movement * LIGHT_SPEED * dt;
+        self.light_uniform.position = light_position.into();
         self.queue.write_buffer(
-            &self.instance_buffer,
+            &self.light_buffer,
             0,
-            bytemuck::cast_slice(&instance_data)
+            bytemuck::cast_slice(&[self.light_uniform]),
         );
-
-        self.camera_controller
-            .update_camera(&mut self.camera_staging.camera);
-        // self.camera_staging.rotation += cgmath::Deg(2.);
+        self.shadow_map.update(&self.queue, light_position);
+    }
+    /// Moves and recolors the scene's point light.
+    pub fn set_light(&mut self, position: [f32; 3], color: [f32; 3], intensity: f32) {
+        self.light_uniform.position = position;
+        self.light_uniform.color = color;
+        self.light_uniform.intensity = intensity;
+        self.queue.write_buffer(
+            &self.light_buffer,
+            0,
+            bytemuck::cast_slice(&[self.light_uniform]),
+        );
+        self.shadow_map.update(&self.queue, position.into());
+    }
+    /// Points the camera from `eye` at `target`, with +Y up.
+    pub fn set_camera(&mut self, eye: [f32; 3], target: [f32; 3]) {
+        let camera = &mut self.camera_staging.camera;
+        camera.eye = eye.into();
+        camera.target = target.into();
+        camera.up = cgmath::Vector3::unit_y();
+        self.write_camera();
+    }
+    pub fn projection(&self) -> Projection {
+        self.camera_staging.camera.projection
+    }
+    /// Switches the camera between perspective and orthographic projection.
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.camera_staging.camera.projection = projection;
+        self.write_camera();
+    }
+    fn write_camera(&mut self) {
         self.camera_staging.update_camera(&mut self.camera_uniform);
         self.queue.write_buffer(
             &self.camera_buffer,
@@ -780,85 +1732,1130 @@ impl State {
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
     }
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+    pub fn shadow_settings(&self) -> shadow::ShadowSettings {
+        self.shadow_map.settings()
+    }
+    /// Changes the shadow map resolution, depth bias or light frustum.
+    pub fn set_shadow_settings(&mut self, settings: shadow::ShadowSettings) {
+        self.shadow_map.set_settings(&self.device, settings);
+        self.shadow_map
+            .update(&self.queue, self.light_uniform.position.into());
+    }
+    /// Loads an OBJ model that replaces the pentagon in the instanced scene.
+    pub fn load_model(&mut self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let path = path.as_ref();
+        self.model = Some(model::Model::load(
+            &self.device,
+            &self.queue,
+            path,
+            &self.texture_bind_group_layout,
+        )?);
+        self.mesh_instances.clear();
+        if self.scene_graph.is_some() {
+            // Regroup the nodes by the new model's meshes
+            self.sync_scene_graph(true);
+        } else {
+            self.take_gpu_spin();
+            self.upload_gpu_instances();
+        }
+        self.model_source = Some(ModelSource::Obj(path.to_path_buf()));
+        Ok(())
+    }
+    /// Rebuilds the pipelines in the pipeline cache whenever one of their shaders in `dir`
+    /// changes on disk: the scene, challenge, light, tonemap, post-processing and skybox
+    /// shaders, and `fullscreen.wgsl` which the tonemap and post-processing shaders share.
+    /// Shaders with errors are logged and skipped.
+    ///
+    /// `shadow.wgsl`, `instance_compute.wgsl` and `equirect_to_cube.wgsl` aren't reloaded.
+    /// Their pipelines are built outside the cache: the shadow pipeline is depth-only with a
+    /// depth bias, the instance pipelines are compute pipelines, and the cubemap conversion
+    /// only runs while a sky is loaded.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn watch_shaders(&mut self, dir: impl AsRef<std::path::Path>) {
+        let dir = dir.as_ref();
+        let mut watcher = hot_reload::ShaderWatcher::new();
+        watcher.watch(dir.join("shader.wgsl"), include_str!("shader.wgsl"));
+        watcher.watch(dir.join("challenge.wgsl"), include_str!("challenge.wgsl"));
+        watcher.watch(dir.join("light.wgsl"), include_str!("light.wgsl"));
+        watcher.watch(dir.join("skybox.wgsl"), include_str!("skybox.wgsl"));
+        for (file, source) in [
+            ("tonemap.wgsl", include_str!("tonemap.wgsl")),
+            (
+                "bloom_downsample.wgsl",
+                include_str!("bloom_downsample.wgsl"),
+            ),
+            ("bloom_upsample.wgsl", include_str!("bloom_upsample.wgsl")),
+            ("color_grading.wgsl", include_str!("color_grading.wgsl")),
+            ("vignette.wgsl", include_str!("vignette.wgsl")),
+            ("fxaa.wgsl", include_str!("fxaa.wgsl")),
+        ] {
+            watcher.watch_with_prelude(
+                dir.join(file),
+                dir.join("fullscreen.wgsl"),
+                post::fullscreen_shader(source),
+            );
+        }
+        self.shader_watcher = Some(watcher);
+    }
+    /// Replaces the scene with the meshes and node instances of a glTF file.
+    /// A camera in the file replaces the current camera.
+    pub fn load_gltf(&mut self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let path = path.as_ref();
+        let scene = gltf_scene::GltfScene::load(
+            &self.device,
+            &self.queue,
+            path,
+            &self.texture_bind_group_layout,
+            self.config.width as f32 / self.config.height as f32,
+        )?;
+        if scene.instances.is_empty() {
+            bail!("glTF scene has no mesh nodes");
+        }
+
+        if let Some(camera) = scene.camera {
+            self.camera_staging.camera = camera;
+            self.write_camera();
+        }
+
+        self.instances = scene.instances;
+        self.mesh_instances = scene.mesh_instances;
+        self.model = Some(scene.model);
+        self.spin_instances = false;
+        self.scene_graph = None;
+        self.upload_instances();
+        self.model_source = Some(ModelSource::Gltf(path.to_path_buf()));
+        Ok(())
+    }
+    /// Replaces the scene with the one described in a RON or JSON scene file, see
+    /// [`scene_file::SceneDesc`].
+    pub fn load_scene(&mut self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let path = path.as_ref();
+        let desc = scene_file::SceneDesc::load(path)?;
+        let base_dir = path.parent().unwrap_or_else(|| std::path::Path::new(""));
+        let (model, instances, mesh_instances) = desc
+            .build(
+                &self.device,
+                &self.queue,
+                &self.texture_bind_group_layout,
+                base_dir,
+            )
+            .with_context(|| format!("Failed to load scene {}", path.display()))?;
+
+        self.camera_staging.camera =
+            desc.camera(self.config.width as f32 / self.config.height as f32);
+        self.write_camera();
+        self.clear_color = desc.clear_color();
+
+        self.instances = instances;
+        self.mesh_instances = mesh_instances;
+        self.model = Some(model);
+        self.spin_instances = false;
+        self.scene_graph = None;
+        self.upload_instances();
+        self.model_source = Some(ModelSource::Scene(path.to_path_buf()));
+        Ok(())
+    }
+    /// Whether the device was lost, so [`Self::recover_device`] has to be called before the
+    /// next frame. It's noticed in the device's errors and in [`Self::update`] and
+    /// [`Self::render`], see [`device_lost`].
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.is_lost()
+    }
+    /// Replaces the device (lost or not) with a new one from an adapter found the same way,
+    /// and recreates every buffer, texture and pipeline on it. The camera, light, instances,
+    /// scene graph and settings carry on; the model is loaded again from its file.
+    pub async fn recover_device(&mut self) -> Result<(), InitError> {
+        let surface = match &self.target {
+            RenderTarget::Surface(surface) => Some(surface),
+            RenderTarget::Offscreen(_) => None,
+        };
+        let adapter = Self::request_adapter(&self.instance, self.adapter_request, surface).await?;
+        log::info!("Recovering on adapter {:?}", adapter.get_info());
+        let (device, queue) = Self::request_device(&adapter).await?;
+
+        // Built with an offscreen target, which is swapped for the window's surface below
+        let render_target =
+            texture::Texture::create_render_target(&device, &self.config, "render_target");
+        let mut state = Self::from_device(
+            self.instance.clone(),
+            self.adapter_request,
+            &adapter,
+            device,
+            queue,
+            self.config.clone(),
+            RenderTarget::Offscreen(render_target),
+        )
+        .await?;
+        if let RenderTarget::Surface(surface) = &self.target {
+            surface.configure(&state.device, &state.config);
+            std::mem::swap(&mut state.target, &mut self.target);
+        }
+        let mut old = std::mem::replace(self, state);
+
+        if let Some(source) = old.model_source.take() {
+            let loaded = match &source {
+                ModelSource::Obj(path) => self.load_model(path),
+                ModelSource::Gltf(path) => self.load_gltf(path),
+                ModelSource::Scene(path) => self.load_scene(path),
+            };
+            if let Err(err) = loaded {
+                log::error!(
+                    "Couldn't reload the model after losing the device: {:?}",
+                    err
+                );
+                old.mesh_instances.clear();
+            }
+        }
+
+        old.take_gpu_spin();
+        let tonemap_settings = old.tonemap_settings();
+        let post_settings = old.post_settings();
+        self.size = old.size;
+        self.camera_staging = old.camera_staging;
+        self.camera_controller = old.camera_controller;
+        self.fly_camera_controller = old.fly_camera_controller;
+        self.fly_mode = old.fly_mode;
+        self.alternate_projection = old.alternate_projection;
+        self.light_movement = old.light_movement;
+        self.clear_color = old.clear_color;
+        self.instances = old.instances;
+        self.mesh_instances = old.mesh_instances;
+        self.spin_instances = old.spin_instances;
+        self.scene_graph = old.scene_graph;
+        self.frustum_culling = old.frustum_culling;
+        self.use_gpu_instances = old.use_gpu_instances && self.gpu_instances.is_some();
+        self.space_down = old.space_down;
+        self.last_frame = old.last_frame;
+        self.frame_delta = old.frame_delta;
+        self.set_tonemap_settings(tonemap_settings);
+        self.set_post_settings(post_settings);
+        if let Some(path) = old.color_lut_path.take() {
+            if let Err(err) = self.load_color_lut(&path) {
+                log::error!(
+                    "Couldn't reload the color LUT after losing the device: {:?}",
+                    err
+                );
+            }
+        }
+        self.show_skybox = old.show_skybox;
+        let reloaded = match old.skybox_source.take() {
+            Some(skybox::SkyboxSource::Equirectangular(path)) => {
+                self.load_skybox_equirectangular(path)
+            }
+            Some(skybox::SkyboxSource::Faces(paths)) => self.load_skybox_faces(paths),
+            Some(skybox::SkyboxSource::BuiltIn) | None => Ok(()),
+        };
+        if let Err(err) = reloaded {
+            log::error!(
+                "Couldn't reload the skybox after losing the device: {:?}",
+                err
+            );
+        }
+        self.set_hdr(old.use_hdr);
+        self.set_msaa_samples(old.requested_sample_count);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.shader_watcher = old.shader_watcher;
+            if let Some(watcher) = &mut self.shader_watcher {
+                watcher.pipelines_rebuilt();
+            }
+        }
+
+        let light = old.light_uniform;
+        self.set_light(light.position, light.color, light.intensity);
+        self.set_shadow_settings(old.shadow_map.settings());
+        self.write_camera();
+        self.upload_instances();
+        Ok(())
+    }
+    /// Replaces the instances with a `per_row` x `per_row` grid, e.g. to see how drawing
+    /// scales with the instance count.
+    pub fn set_instance_grid(&mut self, per_row: u32) {
+        self.instances = instance_grid(per_row);
+        self.mesh_instances.clear();
+        self.scene_graph = None;
+        self.upload_instances();
+    }
+    /// Draws the nodes of `graph` instead of the current instances, each node with the
+    /// mesh of the loaded model it names (or the pentagon for mesh 0). The graph's spinning
+    /// nodes are animated in place of the instance spin.
+    pub fn set_scene_graph(&mut self, graph: scene::SceneGraph) {
+        self.scene_graph = Some(graph);
+        self.spin_instances = false;
+        self.sync_scene_graph(true);
+    }
+    /// The graph set with [`Self::set_scene_graph`]. Changes to it are drawn after the
+    /// next [`Self::update`].
+    pub fn scene_graph_mut(&mut self) -> Option<&mut scene::SceneGraph> {
+        self.scene_graph.as_mut()
+    }
+    /// Takes the scene graph's changed transforms over into `instances`. Unless `regroup` is
+    /// set, the buffers are rewritten in place when no node moved to another mesh.
+    fn sync_scene_graph(&mut self, regroup: bool) {
+        let Some(graph) = &mut self.scene_graph else {
+            return;
+        };
+        if graph.update_world_transforms() == 0 && !regroup {
+            return;
+        }
+        let mesh_count = self.model.as_ref().map_or(1, |model| model.meshes.len());
+        let (instances, mesh_instances) = graph.instances(mesh_count);
+
+        self.instances = instances;
+        if !regroup && mesh_instances == self.mesh_instances {
+            // The CPU path rewrites its instance buffer every update anyway
+            if let Some(gpu_instances) = self.active_gpu_instances() {
+                gpu_instances.write_instances(&self.queue, &self.instances);
+            }
+        } else {
+            self.mesh_instances = mesh_instances;
+            self.upload_instances();
+        }
+    }
+    /// Switches between animating and culling the instances with compute shaders and doing
+    /// it on the CPU. Returns whether the compute path is now used, which it can't be where
+    /// compute shaders are unavailable (e.g. WebGL2).
+    pub fn set_gpu_instances(&mut self, enabled: bool) -> bool {
+        if enabled == self.use_gpu_instances || self.gpu_instances.is_none() {
+            return self.use_gpu_instances;
+        }
+        if enabled {
+            self.use_gpu_instances = true;
+            self.upload_gpu_instances();
+        } else {
+            // Carry on from where the GPU got to
+            self.take_gpu_spin();
+            self.use_gpu_instances = false;
+            self.upload_instances();
+        }
+        self.use_gpu_instances
+    }
+    pub fn msaa_samples(&self) -> u32 {
+        self.sample_count
+    }
+    /// Turns MSAA on with `count` samples per pixel (1 turns it off). Counts the adapter
+    /// doesn't support for the scene's format are changed to the nearest one that is (the
+    /// higher one on a tie), which is returned.
+    pub fn set_msaa_samples(&mut self, count: u32) -> u32 {
+        self.requested_sample_count = count;
+        let supported = self.nearest_sample_count(count);
+        if supported != self.sample_count {
+            self.sample_count = supported;
+            self.retarget_scene_pipelines();
+        }
+        supported
+    }
+    /// The MSAA sample counts the scene can be drawn with, which depend on whether HDR is on.
+    fn supported_sample_counts(&self) -> &[u32] {
+        if self.use_hdr {
+            &self.hdr_sample_counts
+        } else {
+            &self.surface_sample_counts
+        }
+    }
+    fn nearest_sample_count(&self, count: u32) -> u32 {
+        let log2 = |count: u32| count.max(1).ilog2() as i32;
+        let supported = self
+            .supported_sample_counts()
+            .iter()
+            .copied()
+            .min_by_key(|&supported| {
+                (
+                    (log2(supported) - log2(count)).abs(),
+                    std::cmp::Reverse(supported),
+                )
+            })
+            .unwrap_or(1);
+        if supported != count {
+            log::warn!(
+                "{}x MSAA isn't supported for {:?}, using {}x",
+                count,
+                self.scene_format(),
+                supported
+            );
+        }
+        supported
+    }
+    /// Draws the scene into an `hdr::HDR_FORMAT` target and tonemaps it into the frame, or
+    /// straight into the frame. Returns whether HDR is now used, which it can't be where the
+    /// adapter can't render to that format (e.g. WebGL2 without float color buffers).
+    pub fn set_hdr(&mut self, enabled: bool) -> bool {
+        if enabled == self.use_hdr || self.hdr_target.is_none() {
+            return self.use_hdr;
+        }
+        self.use_hdr = enabled;
+        // The new format may support different sample counts
+        self.sample_count = self.nearest_sample_count(self.requested_sample_count);
+        self.retarget_scene_pipelines();
+        self.use_hdr
+    }
+    /// The tonemapping used while HDR is on.
+    pub fn tonemap_settings(&self) -> hdr::TonemapSettings {
+        self.hdr_target
+            .as_ref()
+            .map(hdr::HdrTarget::settings)
+            .unwrap_or_default()
+    }
+    pub fn set_tonemap_settings(&mut self, settings: hdr::TonemapSettings) {
+        if let Some(hdr_target) = &mut self.hdr_target {
+            hdr_target.set_settings(&self.queue, settings);
+        }
+    }
+    /// The effects applied to the scene while HDR is on; see `set_hdr`.
+    pub fn post_settings(&self) -> post::PostSettings {
+        self.post
+            .as_ref()
+            .map(post::PostProcess::settings)
+            .unwrap_or_default()
+    }
+    pub fn set_post_settings(&mut self, settings: post::PostSettings) {
+        let Some(post) = &mut self.post else {
+            return;
+        };
+        let bloom_toggled = settings.bloom.enabled != post.settings().bloom.enabled;
+        post.set_settings(&self.queue, settings);
+        // Bloom's passes and textures are only in the graph while it's on
+        if bloom_toggled {
+            self.rebuild_render_graph();
+        }
+    }
+    /// Replaces the color-grading lookup table, an image of N slices of NxN texels side by
+    /// side (see `texture::Texture::lut_from_bytes`). Color grading still has to be enabled
+    /// in the post settings.
+    pub fn load_color_lut(&mut self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let path = path.as_ref();
+        let Some(post) = &mut self.post else {
+            bail!("Color grading needs an HDR target, which this adapter doesn't support");
+        };
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+        let label = path.display().to_string();
+        let lut = texture::Texture::lut_from_bytes(&self.device, &self.queue, &bytes, &label)?;
+        post.set_lut(lut);
+        self.bind_graph_textures();
+        self.color_lut_path = Some(path.to_path_buf());
+        Ok(())
+    }
+    /// Replaces the sky with a cubemap from six square images of the same size, in the order
+    /// +X, -X, +Y, -Y, +Z, -Z.
+    pub fn load_skybox_faces<P: AsRef<std::path::Path>>(&mut self, paths: [P; 6]) -> Result<()> {
+        let paths = paths.map(|path| path.as_ref().to_path_buf());
+        let mut faces = Vec::with_capacity(6);
+        for path in &paths {
+            faces.push(
+                std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?,
+            );
+        }
+        let label = paths[0].display().to_string();
+        let cubemap = texture::Texture::cubemap_from_bytes(
+            &self.device,
+            &self.queue,
+            std::array::from_fn(|i| &faces[i][..]),
+            &label,
+        )?;
+        self.set_skybox_cubemap(cubemap);
+        self.skybox_source = Some(skybox::SkyboxSource::Faces(paths));
+        Ok(())
+    }
+    /// Replaces the sky with an equirectangular image, a Radiance `.hdr` file or a PNG or
+    /// JPEG, converted into a cubemap on the GPU. Adapters without HDR support clamp the sky
+    /// to 1.
+    pub fn load_skybox_equirectangular(&mut self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let path = path.as_ref();
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+        let label = path.display().to_string();
+        let cubemap = texture::Texture::cubemap_from_equirectangular(
+            &self.device,
+            &self.queue,
+            &bytes,
+            skybox::EQUIRECT_FACE_SIZE,
+            skybox::equirect_format(self.hdr_target.is_some()),
+            &label,
+        )?;
+        self.set_skybox_cubemap(cubemap);
+        self.skybox_source = Some(skybox::SkyboxSource::Equirectangular(path.to_path_buf()));
+        Ok(())
+    }
+    /// Shows or hides the skybox; the background is `clear_color` while it's hidden.
+    pub fn set_skybox_visible(&mut self, visible: bool) {
+        self.show_skybox = visible;
+    }
+    fn set_skybox_cubemap(&mut self, cubemap: texture::Texture) {
+        self.skybox.set_cubemap(&self.device, cubemap);
+    }
+    fn active_hdr_target(&self) -> Option<&hdr::HdrTarget> {
+        self.hdr_target.as_ref().filter(|_| self.use_hdr)
+    }
+    /// The format the scene is drawn in.
+    fn scene_format(&self) -> wgpu::TextureFormat {
+        if self.active_hdr_target().is_some() {
+            hdr::HDR_FORMAT
+        } else {
+            self.config.format
+        }
+    }
+    /// Rebuilds the scene's pipelines and targets for the current format and sample count.
+    fn retarget_scene_pipelines(&mut self) {
+        let format = self.scene_format();
+        for id in [
+            &mut self.render_pipeline,
+            &mut self.render_pipeline_chal,
+            &mut self.light_render_pipeline,
+            &mut self.skybox.pipeline,
+        ] {
+            *id = self
+                .pipelines
+                .retarget(&self.device, *id, format, self.sample_count);
+        }
+        self.rebuild_render_graph();
+    }
+    fn active_gpu_instances(&self) -> Option<&instance_compute::GpuInstances> {
+        self.gpu_instances
+            .as_ref()
+            .filter(|_| self.use_gpu_instances)
+    }
+    /// Recreates the instance buffer for the current `instances`, and the compute path's
+    /// buffers if it's used.
+    fn upload_instances(&mut self) {
+        let instance_data = self
+            .instances
+            .iter()
+            .map(Instance::to_raw)
+            .collect::<Vec<_>>();
+        self.instance_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Instance Buffer"),
+                contents: bytemuck::cast_slice(&instance_data),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            });
+        self.upload_gpu_instances();
+    }
+    fn upload_gpu_instances(&mut self) {
+        let groups = self.instance_groups();
+        let gpu_instances = self
+            .gpu_instances
+            .as_mut()
+            .filter(|_| self.use_gpu_instances);
+        if let Some(gpu_instances) = gpu_instances {
+            gpu_instances.upload(&self.device, &self.instances, &groups);
+        }
+    }
+    /// Applies the spin the compute path has accumulated to `instances`.
+    fn take_gpu_spin(&mut self) {
+        let gpu_instances = self
+            .gpu_instances
+            .as_mut()
+            .filter(|_| self.use_gpu_instances);
+        if let Some(gpu_instances) = gpu_instances {
+            let angle = gpu_instances.take_spin();
+            for instance in &mut self.instances {
+                instance.spin(angle);
+            }
+        }
+    }
+    /// Each mesh to draw (or the pentagon) with its bounds and instances.
+    fn instance_groups(&self) -> Vec<instance_compute::InstanceGroup> {
+        let all_instances = 0..self.instances.len() as u32;
+        match &self.model {
+            Some(model) => model
+                .meshes
+                .iter()
+                .enumerate()
+                .map(|(i, mesh)| instance_compute::InstanceGroup {
+                    bounds: mesh.bounds,
+                    instances: self.mesh_instances.get(i).unwrap_or(&all_instances).clone(),
+                    index_count: mesh.num_elements,
+                })
+                .collect(),
+            None => vec![instance_compute::InstanceGroup {
+                bounds: self.pentagon_bounds,
+                instances: all_instances,
+                index_count: self.num_indices,
+            }],
+        }
+    }
+    /// Switches between the instanced happy-tree scene and the challenge cube.
+    pub fn show_challenge(&mut self, show: bool) {
+        self.space_down = show;
+    }
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        if self.active_gpu_instances().is_some() {
+            // The counts stay on the GPU until they're asked for
+            self.culling_stats = culling::CullingStats::default();
+            self.gpu_culling_stats = !self.space_down;
+        } else {
+            self.gpu_culling_stats = false;
+            self.cull_instances();
+        }
+        // A lost device makes wgpu panic while the frame is acquired or submitted; the frame
+        // is skipped and `is_device_lost` reports it
+        let drawn = self.device_lost.catch(|| match &self.target {
+            RenderTarget::Surface(surface) => {
+                let output = surface.get_current_texture()?;
+                let view = output
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                self.draw(&view);
+                output.present();
+                Ok(())
+            }
+            RenderTarget::Offscreen(render_target) => {
+                self.draw(&render_target.view);
+                Ok(())
+            }
+        });
+        if let Some(result) = drawn {
+            result?;
+        }
+
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {}
+            else {
+                let elapsed = self.last_frame.unwrap().elapsed();
+                // Headless captures (golden tests, benchmarks) would only flood the output
+                if matches!(self.target, RenderTarget::Surface(_)) {
+                    let fps = 1. / elapsed.as_secs_f64();
+                    println!("FPS: {:.0}", fps);
+                }
+                self.frame_delta = elapsed.min(MAX_FRAME_DELTA);
+                self.last_frame = Some(std::time::Instant::now());
+            }
+        }
+
+        Ok(())
+    }
+    fn draw(&self, view: &wgpu::TextureView) {
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
+        self.render_graph.execute(&mut encoder, view, self);
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+    /// Declares the frame's passes for the current sample count, HDR setting and whether
+    /// bloom is on. The graph allocates the depth texture, the MSAA color target and the HDR
+    /// scene and post-processing textures.
+    fn build_render_graph(&mut self) -> Result<(), render_graph::GraphError> {
+        use render_graph::{PassDesc, TextureDesc};
+
+        let mut graph = render_graph::RenderGraph::new();
+        let frame = graph.import();
+        let visible_instances = graph.import();
+        let shadow_map = graph.import();
+        let depth = graph.add_texture(
+            TextureDesc::new(
+                "depth_texture",
+                texture::Texture::DEPTH_FORMAT,
+                wgpu::TextureUsages::RENDER_ATTACHMENT,
+            )
+            .sample_count(self.sample_count),
+        );
+        let msaa_target = (self.sample_count > 1).then(|| {
+            graph.add_texture(
+                TextureDesc::new(
+                    "msaa_target",
+                    self.scene_format(),
+                    wgpu::TextureUsages::RENDER_ATTACHMENT,
+                )
+                .sample_count(self.sample_count),
+            )
+        });
+        let hdr_scene = self
+            .active_hdr_target()
+            .map(|_| graph.add_texture(hdr::HdrTarget::scene_texture()));
+        let scene = hdr_scene.unwrap_or(frame);
+
+        graph.add_pass(
+            PassDesc::new("Instance Culling").write(visible_instances),
+            State::dispatch_gpu_instances,
+        );
+        graph.add_pass(
+            PassDesc::new("Shadow Pass")
+                .read(visible_instances)
+                .write(shadow_map),
+            State::draw_shadow_pass,
+        );
+        let mut scene_pass = PassDesc::new("Render Pass")
+            .read(visible_instances)
+            .read(shadow_map)
+            .write(depth)
+            .write(scene);
+        if let Some(msaa_target) = msaa_target {
+            scene_pass = scene_pass.write(msaa_target);
+        }
+        graph.add_pass(scene_pass, move |state: &State, pass| {
+            state.draw_scene_pass(pass, depth, msaa_target, hdr_scene)
+        });
+        let mut skybox_pass = PassDesc::new("Skybox").read(depth).write(scene);
+        if let Some(msaa_target) = msaa_target {
+            skybox_pass = skybox_pass.write(msaa_target);
+        }
+        graph.add_pass(skybox_pass, move |state: &State, pass| {
+            state.draw_skybox_pass(pass, depth, msaa_target, hdr_scene)
+        });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(self.clear_color),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: None,
+        let post_textures = hdr_scene.and_then(|hdr_scene| {
+            let post = self.post.as_ref()?;
+            Some(post.add_textures(&mut graph, hdr_scene, self.config.format))
+        });
+        let tonemap_input = match &post_textures {
+            Some(textures) => textures.bloomed.unwrap_or(textures.scene),
+            None => scene,
+        };
+        if let Some(textures) = &post_textures {
+            if let Some(bloomed) = textures.bloomed {
+                let mut bloom_pass = PassDesc::new("Bloom").read(scene).write(bloomed);
+                for &level in &textures.bloom_levels {
+                    bloom_pass = bloom_pass.write(level);
+                }
+                let textures = textures.clone();
+                graph.add_pass(bloom_pass, move |state: &State, pass| {
+                    if let Some(post) = &state.post {
+                        post.draw_bloom(pass, &state.pipelines, &textures);
+                    }
+                });
+            }
+            // Tonemapping draws into the post-processing chain's input, or the frame if
+            // every effect after it is off
+            let [post_input, post_output] = textures.ldr;
+            let tonemap_textures = textures.clone();
+            graph.add_pass(
+                PassDesc::new("Tonemap")
+                    .read(tonemap_input)
+                    .write(post_input)
+                    .write(frame),
+                move |state: &State, pass| {
+                    if let (Some(hdr_target), Some(post)) = (&state.hdr_target, &state.post) {
+                        let target = post.tonemap_target(pass, &tonemap_textures);
+                        hdr_target.draw(pass.encoder, &state.pipelines, target);
+                    }
+                },
+            );
+            let textures = textures.clone();
+            graph.add_pass(
+                PassDesc::new("Post-processing")
+                    .read(post_input)
+                    .write(post_output)
+                    .write(frame),
+                move |state: &State, pass| {
+                    if let Some(post) = &state.post {
+                        post.draw_ldr(pass, &state.pipelines, &textures);
+                    }
+                },
+            );
+        } else if hdr_scene.is_some() {
+            graph.add_pass(
+                PassDesc::new("Tonemap").read(scene).write(frame),
+                |state: &State, pass| {
+                    if let Some(hdr_target) = &state.hdr_target {
+                        hdr_target.draw(pass.encoder, &state.pipelines, pass.output);
+                    }
+                },
+            );
+        }
+
+        graph.compile(&self.device, self.config.width, self.config.height)?;
+        self.render_graph = graph;
+        self.hdr_textures = hdr_scene.map(|_| HdrTextures {
+            tonemap_input,
+            post: post_textures,
+        });
+        self.bind_graph_textures();
+        Ok(())
+    }
+    /// Rebuilds the graph after a setting changed. The passes are the same whichever way
+    /// the settings are, so failing here means one of them is declared wrong.
+    fn rebuild_render_graph(&mut self) {
+        if let Err(err) = self.build_render_graph() {
+            log::error!("Couldn't rebuild the render graph: {}", err);
+        }
+    }
+    /// Points the tonemapping and post-processing bind groups at the graph's textures, which
+    /// it replaces whenever it's compiled or resized.
+    fn bind_graph_textures(&mut self) {
+        let Some(textures) = &self.hdr_textures else {
+            return;
+        };
+        if let Some(hdr_target) = &mut self.hdr_target {
+            let input = self
+                .render_graph
+                .texture(textures.tonemap_input)
+                .expect("the HDR scene is allocated by the graph");
+            hdr_target.bind(&self.device, input);
+        }
+        if let (Some(post), Some(post_textures)) = (&mut self.post, &textures.post) {
+            post.bind(&self.device, &self.render_graph, post_textures);
+        }
+    }
+    fn dispatch_gpu_instances(&self, pass: &mut render_graph::PassContext) {
+        if let Some(gpu_instances) = self.active_gpu_instances().filter(|_| !self.space_down) {
+            let frustum = culling::Frustum::from_matrix(self.camera_uniform.view_proj.into());
+            gpu_instances.dispatch(
+                &self.queue,
+                pass.encoder,
+                self.frustum_culling.then_some(&frustum),
+            );
+        }
+    }
+    fn draw_shadow_pass(&self, pass: &mut render_graph::PassContext) {
+        if !self.space_down {
+            let mut shadow_pass = self.shadow_map.begin_pass(pass.encoder);
+            self.draw_shadow_casters(&mut shadow_pass);
+        }
+    }
+    fn draw_scene_pass(
+        &self,
+        pass: &mut render_graph::PassContext,
+        depth: render_graph::ResourceId,
+        msaa_target: Option<render_graph::ResourceId>,
+        hdr_scene: Option<render_graph::ResourceId>,
+    ) {
+        // With HDR the scene is tonemapped into the frame afterwards
+        let scene_view = match hdr_scene {
+            Some(hdr_scene) => pass.texture(hdr_scene),
+            None => pass.output,
+        };
+        // With MSAA the pass draws into the multisampled target and resolves into the scene's
+        let (color_view, resolve_target) = match msaa_target {
+            Some(msaa_target) => (pass.texture(msaa_target), Some(scene_view)),
+            None => (scene_view, None),
+        };
+        let depth_view = pass.texture(depth);
+        let mut render_pass = pass.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: color_view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.clear_color),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
                 }),
-            });
+                stencil_ops: None,
+            }),
+        });
 
-            if self.space_down {
-                self.vertex_buffer_chal =
-                    self.device
-                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: Some("Challenge Vertex Buffer"),
-                            contents: bytemuck::cast_slice(VERTICES_CHAL),
-                            usage: wgpu::BufferUsages::VERTEX,
-                        });
-
-                render_pass.set_pipeline(&self.render_pipeline_chal);
-                render_pass.set_bind_group(0, &self.diffuse_bind_group_chal, &[]);
-                render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-                render_pass.set_vertex_buffer(0, self.vertex_buffer_chal.slice(..));
-                render_pass
-                    .set_index_buffer(self.index_buffer_chal.slice(..), wgpu::IndexFormat::Uint16);
-                render_pass.draw_indexed(0..self.num_indices_chal, 0, 0..1);
+        if self.space_down {
+            render_pass.set_pipeline(&self.pipelines[self.render_pipeline_chal]);
+            render_pass.set_bind_group(0, &self.diffuse_bind_group_chal, &[]);
+            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.light_bind_group, &[]);
+            render_pass.set_bind_group(3, &self.shadow_map.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer_chal.slice(..));
+            render_pass
+                .set_index_buffer(self.index_buffer_chal.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..self.num_indices_chal, 0, 0..1);
+        } else {
+            self.draw_light(&mut render_pass);
+
+            render_pass.set_pipeline(&self.pipelines[self.render_pipeline]);
+            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.light_bind_group, &[]);
+            render_pass.set_bind_group(3, &self.shadow_map.bind_group, &[]);
+            self.draw_ground(&mut render_pass);
+
+            if let Some(gpu_instances) = self.active_gpu_instances() {
+                self.draw_gpu_instances(&mut render_pass, gpu_instances);
+            } else if let Some(model) = &self.model {
+                render_pass.set_vertex_buffer(1, self.visible_instance_buffer.slice(..));
+                for (mesh, instances) in model.meshes.iter().zip(&self.visible_instances) {
+                    let material = &model.materials[mesh.material];
+                    render_pass.draw_mesh_instanced(mesh, material, instances.clone());
+                }
             } else {
-                render_pass.set_pipeline(&self.render_pipeline);
+                render_pass.set_vertex_buffer(1, self.visible_instance_buffer.slice(..));
                 render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-                render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
                 render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-                render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
                 render_pass
                     .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as u32);
+                render_pass.draw_indexed(0..self.num_indices, 0, self.visible_instances[0].clone());
             }
         }
+    }
+    /// Fills in the background the scene pass left, testing against its depth.
+    fn draw_skybox_pass(
+        &self,
+        pass: &mut render_graph::PassContext,
+        depth: render_graph::ResourceId,
+        msaa_target: Option<render_graph::ResourceId>,
+        hdr_scene: Option<render_graph::ResourceId>,
+    ) {
+        if !self.show_skybox {
+            return;
+        }
+        let scene_view = match hdr_scene {
+            Some(hdr_scene) => pass.texture(hdr_scene),
+            None => pass.output,
+        };
+        let (color_view, resolve_target) = match msaa_target {
+            Some(msaa_target) => (pass.texture(msaa_target), Some(scene_view)),
+            None => (scene_view, None),
+        };
+        let depth_view = pass.texture(depth);
+        let mut render_pass = pass.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Skybox Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: color_view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            // Read-only, the sky doesn't write depth
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: None,
+                stencil_ops: None,
+            }),
+        });
+        self.skybox
+            .draw(&mut render_pass, &self.pipelines, &self.camera_bind_group);
+    }
+    fn create_visible_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Visible Instance Buffer"),
+            size: (capacity.max(1) * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+    /// Tests each instance's bounding sphere against the camera frustum and uploads the
+    /// visible ones, grouped per mesh, to `visible_instance_buffer`.
+    fn cull_instances(&mut self) {
+        let frustum = culling::Frustum::from_matrix(self.camera_uniform.view_proj.into());
+        let mut visible = Vec::new();
+        let mut stats = culling::CullingStats::default();
+        self.visible_instances.clear();
+        for group in self.instance_groups() {
+            let start = visible.len() as u32;
+            let instances = group.instances.start as usize..group.instances.end as usize;
+            for instance in &self.instances[instances] {
+                if !self.frustum_culling
+                    || frustum.intersects_sphere(&group.bounds.transformed(instance))
+                {
+                    visible.push(instance.to_raw());
+                    stats.visible += 1;
+                } else {
+                    stats.culled += 1;
+                }
+            }
+            self.visible_instances.push(start..visible.len() as u32);
+        }
+        self.culling_stats = stats;
 
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-
-        cfg_if::cfg_if! {
-            if #[cfg(target_arch = "wasm32")] {}
-            else {
-                let elapsed_seconds = self.last_frame.unwrap().elapsed().as_secs_f64();
-                let fps = 1. / elapsed_seconds;
-                println!("FPS: {:.0}", fps);
-                self.last_frame = Some(std::time::Instant::now());
+        if visible.len() > self.visible_instance_capacity {
+            self.visible_instance_capacity = visible.len().next_power_of_two();
+            self.visible_instance_buffer =
+                Self::create_visible_instance_buffer(&self.device, self.visible_instance_capacity);
+        }
+        if !visible.is_empty() {
+            self.queue.write_buffer(
+                &self.visible_instance_buffer,
+                0,
+                bytemuck::cast_slice(&visible),
+            );
+        }
+    }
+    /// Draws the instances the compute path found visible, with their indirect draws.
+    fn draw_gpu_instances<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        gpu_instances: &'a instance_compute::GpuInstances,
+    ) {
+        if let Some(model) = &self.model {
+            for (i, mesh) in model.meshes.iter().enumerate() {
+                if let Some((instances, draws, offset)) = gpu_instances.group_draw(i) {
+                    let material = &model.materials[mesh.material];
+                    render_pass.set_vertex_buffer(1, instances);
+                    render_pass.draw_mesh_indirect(mesh, material, draws, offset);
+                }
             }
+        } else if let Some((instances, draws, offset)) = gpu_instances.group_draw(0) {
+            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, instances);
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed_indirect(draws, offset);
         }
+    }
+    /// How many instances the last rendered frame drew and skipped. On the compute path
+    /// this reads the indirect draw counts back, waiting for the GPU to finish the frame.
+    pub fn culling_stats(&self) -> culling::CullingStats {
+        match self.active_gpu_instances() {
+            Some(gpu_instances) if self.gpu_culling_stats => gpu_instances.read_stats(&self.device),
+            _ => self.culling_stats,
+        }
+    }
+    /// Turns frustum culling on or off, e.g. to compare the two.
+    pub fn set_frustum_culling(&mut self, enabled: bool) {
+        self.frustum_culling = enabled;
+    }
+    /// Draws the instances depth-only into the shadow map.
+    fn draw_shadow_casters<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let instance_buffer = self
+            .active_gpu_instances()
+            .and_then(instance_compute::GpuInstances::transformed_buffer)
+            .unwrap_or(&self.instance_buffer);
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        if let Some(model) = &self.model {
+            for (i, mesh) in model.meshes.iter().enumerate() {
+                let instances = match self.mesh_instances.get(i) {
+                    Some(instances) => instances.clone(),
+                    None => 0..self.instances.len() as u32,
+                };
+                render_pass.draw_mesh_geometry_instanced(mesh, instances);
+            }
+        } else {
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as u32);
+        }
+    }
+    /// Draws the ground plane that receives the instances' shadows.
+    fn draw_ground<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_bind_group(0, &self.ground_material.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.ground_vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.ground_instance_buffer.slice(..));
+        render_pass.set_index_buffer(
+            self.ground_index_buffer.slice(..),
+            wgpu::IndexFormat::Uint16,
+        );
+        render_pass.draw_indexed(0..GROUND_INDICES.len() as u32, 0, 0..1);
+    }
+    /// Draws the light as a small unlit cube so its position is visible.
+    fn draw_light<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipelines[self.light_render_pipeline]);
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.light_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.light_vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.light_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..LIGHT_INDICES.len() as u32, 0, 0..1);
+    }
+    /// Renders a frame into the offscreen target and reads it back to CPU memory.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn capture_frame(&mut self) -> Result<image::RgbaImage> {
+        self.render()?;
+
+        let render_target = match &self.target {
+            RenderTarget::Offscreen(render_target) => render_target,
+            RenderTarget::Surface(_) => bail!("Frames can only be captured from a headless State"),
+        };
+
+        let width = self.config.width;
+        let height = self.config.height;
+        let unpadded_bytes_per_row = 4 * width;
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Frame Output Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
 
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Capture Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &render_target.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &output_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let buffer_slice = output_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).ok();
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let pixels = {
+            let data = buffer_slice.get_mapped_range();
+            data.chunks(padded_bytes_per_row as usize)
+                .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        output_buffer.unmap();
+
+        image::RgbaImage::from_raw(width, height, pixels)
+            .context("Frame readback has the wrong size")
+    }
+    /// Renders a frame and writes it to `path` as a PNG.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_frame(&mut self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        self.capture_frame()?.save(path)?;
         Ok(())
     }
 }
 
-#[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
-pub async fn run() {
+/// Entry point for the web build, which has no command line to configure. Setup errors are
+/// shown in the page, where a panic would only reach the console.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(start)]
+pub async fn run_web() {
+    if let Err(err) = run(config::Config::default()).await {
+        let err = anyhow::Error::from(err);
+        log::error!("{:?}", err);
+        show_web_error(&format!("{:#}", err));
+    }
+}
+
+/// Replaces the page's body with `message`.
+#[cfg(target_arch = "wasm32")]
+fn show_web_error(message: &str) {
+    let shown = web_sys::window()
+        .and_then(|win| win.document())
+        .and_then(|doc| {
+            let dst = doc.get_element_by_id("body")?;
+            let text = doc.create_element("p").ok()?;
+            text.set_text_content(Some(&format!(
+                "Couldn't start WebGPU/WebGL rendering: {}",
+                message
+            )));
+            dst.append_child(&text).ok()?;
+            Some(())
+        });
+    if shown.is_none() {
+        log::error!("Couldn't show the error in the page");
+    }
+}
+
+/// Opens a window set up as `config` says and runs the event loop until it closes. Only
+/// returns if the window or the [`State`] can't be set up.
+pub async fn run(config: config::Config) -> Result<(), InitError> {
     cfg_if::cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
             std::panic::set_hook(Box::new(console_error_panic_hook::hook));
@@ -869,12 +2866,23 @@ pub async fn run() {
     }
 
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let mut window_builder = WindowBuilder::new();
+    if let Some(size) = config.window_size() {
+        window_builder = window_builder.with_inner_size(size);
+    }
+    if config.fullscreen {
+        window_builder = window_builder.with_fullscreen(Some(Fullscreen::Borderless(None)));
+    }
+    let window = window_builder
+        .build(&event_loop)
+        .map_err(InitError::Window)?;
 
     #[cfg(target_arch = "wasm32")]
     {
         use winit::dpi::PhysicalSize;
-        window.set_inner_size(PhysicalSize::new(450, 450));
+        if config.window_size().is_none() {
+            window.set_inner_size(PhysicalSize::new(450, 450));
+        }
 
         use winit::platform::web::WindowExtWebSys;
         web_sys::window()
@@ -888,52 +2896,82 @@ pub async fn run() {
             .expect("Couldn't add canvas to doc");
     }
 
-    let mut state = State::new(&window).await;
+    let mut state = State::new(&window, &config).await?;
 
-    event_loop.run(move |event, _, control_flow| {
-        match event {
-            Event::WindowEvent {
-                ref event,
-                window_id,
-            } if window_id == window.id() => {
-                if !state.input(event) {
-                    // UPDATED!
-                    match event {
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            input:
-                                KeyboardInput {
-                                    state: ElementState::Pressed,
-                                    virtual_keycode: Some(VirtualKeyCode::Escape),
-                                    ..
-                                },
-                            ..
-                        } => *control_flow = ControlFlow::Exit,
-                        WindowEvent::Resized(physical_size) => {
-                            state.resize(*physical_size);
-                        }
-                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            state.resize(**new_inner_size);
-                        }
-                        _ => {}
-                    }
+    // Debug builds pick up shader edits in the source tree without a restart
+    #[cfg(not(target_arch = "wasm32"))]
+    if cfg!(debug_assertions) {
+        state.watch_shaders(concat!(env!("CARGO_MANIFEST_DIR"), "/src"));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = &config.file {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let loaded = match extension {
+            Some("gltf" | "glb") => state.load_gltf(path),
+            Some("ron" | "json") => state.load_scene(path),
+            _ => state.load_model(path),
+        };
+        if let Err(err) = loaded {
+            log::error!("Couldn't load {}: {:?}", path.display(), err);
+        }
+    }
+
+    let mut cursor_grabbed = false;
+    event_loop.run(move |event, _, control_flow| match event {
+        Event::WindowEvent {
+            ref event,
+            window_id,
+        } if window_id == window.id() && !state.input(event) => match event {
+            WindowEvent::CloseRequested
+            | WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
+                        ..
+                    },
+                ..
+            } => *control_flow = ControlFlow::Exit,
+            WindowEvent::Resized(physical_size) => {
+                state.resize(*physical_size);
+            }
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                state.resize(**new_inner_size);
+            }
+            _ => {}
+        },
+        Event::DeviceEvent { ref event, .. } => state.device_input(event),
+        Event::RedrawRequested(window_id) if window_id == window.id() => {
+            #[cfg(not(target_arch = "wasm32"))]
+            if state.is_device_lost() {
+                if let Err(err) = pollster::block_on(state.recover_device()) {
+                    log::error!("Couldn't recover from losing the device: {:?}", err);
+                    *control_flow = ControlFlow::Exit;
+                    return;
                 }
+                log::info!("Recovered from losing the device");
             }
-            Event::RedrawRequested(window_id) if window_id == window.id() => {
-                state.update();
-                match state.render() {
-                    Ok(_) => {}
-                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
-                        state.resize(state.size)
-                    }
-                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    Err(wgpu::SurfaceError::Timeout) => log::warn!("Surface timeout"),
+            state.update();
+            match state.render() {
+                Ok(_) => {}
+                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                    state.resize(state.size)
                 }
+                Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+                Err(wgpu::SurfaceError::Timeout) => log::warn!("Surface timeout"),
             }
-            Event::RedrawEventsCleared => {
-                window.request_redraw();
+        }
+        Event::RedrawEventsCleared => {
+            if state.wants_cursor_grab() != cursor_grabbed {
+                cursor_grabbed = state.wants_cursor_grab();
+                if let Err(err) = window.set_cursor_grab(cursor_grabbed) {
+                    log::warn!("Couldn't grab the cursor: {}", err);
+                }
+                window.set_cursor_visible(!cursor_grabbed);
             }
-            _ => {}
+            window.request_redraw();
         }
+        _ => {}
     });
 }
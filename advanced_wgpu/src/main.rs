@@ -1,9 +1,25 @@
-use advanced_wgpu::run;
+use advanced_wgpu::{config, run};
 
 fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.iter().any(|arg| arg == "--help") {
+        println!("{}", config::USAGE);
+        return;
+    }
+    let config = match config::Config::from_args(args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{:#}\n\n{}", err, config::USAGE);
+            std::process::exit(2);
+        }
+    };
+
     println!("Starting...");
 
-    pollster::block_on(run());
+    if let Err(err) = pollster::block_on(run(config)) {
+        eprintln!("Error: {:?}", anyhow::Error::from(err));
+        std::process::exit(1);
+    }
 
     println!("Exiting...");
 }
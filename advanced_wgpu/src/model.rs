@@ -0,0 +1,405 @@
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::*;
+use wgpu::util::DeviceExt;
+
+use crate::culling::BoundingSphere;
+use crate::texture;
+use crate::Vertex;
+
+pub struct Material {
+    pub name: String,
+    pub diffuse_texture: texture::Texture,
+    /// Tangent-space normals, stored linearly (see [`texture::TextureOptions::srgb`]).
+    pub normal_texture: texture::Texture,
+    pub bind_group: wgpu::BindGroup,
+}
+impl Material {
+    pub fn new(
+        device: &wgpu::Device,
+        name: &str,
+        diffuse_texture: texture::Texture,
+        normal_texture: texture::Texture,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normal_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
+                },
+            ],
+            label: Some(name),
+        });
+
+        Self {
+            name: name.to_string(),
+            diffuse_texture,
+            normal_texture,
+            bind_group,
+        }
+    }
+    /// A 1x1 texture for materials without a diffuse map.
+    pub(crate) fn solid_color_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+    ) -> Result<texture::Texture> {
+        let image =
+            image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(color)));
+        texture::Texture::from_image(device, queue, &image, Some("solid_color"))
+    }
+    /// A 1x1 normal map that leaves the vertex normals unchanged.
+    pub(crate) fn flat_normal_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<texture::Texture> {
+        let image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            1,
+            1,
+            image::Rgba([128, 128, 255, 255]),
+        ));
+        texture::Texture::from_image_with_options(
+            device,
+            queue,
+            &image,
+            Some("flat_normal"),
+            texture::TextureOptions {
+                srgb: false,
+                ..Default::default()
+            },
+        )
+    }
+}
+
+pub struct Mesh {
+    pub name: String,
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub num_elements: u32,
+    pub material: usize,
+    pub bounds: BoundingSphere,
+}
+impl Mesh {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        name: &str,
+        vertices: &[Vertex],
+        indices: &[u32],
+        material: usize,
+    ) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", name)),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", name)),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            name: name.to_string(),
+            vertex_buffer,
+            index_buffer,
+            num_elements: indices.len() as u32,
+            material,
+            bounds: BoundingSphere::from_vertices(vertices),
+        }
+    }
+}
+
+pub struct Model {
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Material>,
+}
+impl Model {
+    /// Loads an OBJ file and the MTL materials it references.
+    /// Texture paths in the MTL file are resolved relative to the OBJ file.
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let parent = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let (obj_models, obj_materials) = tobj::load_obj(
+            path,
+            &tobj::LoadOptions {
+                triangulate: true,
+                single_index: true,
+                ..Default::default()
+            },
+        )
+        .with_context(|| format!("Failed to load OBJ file {}", path.display()))?;
+        let obj_materials = obj_materials
+            .with_context(|| format!("Failed to load materials for {}", path.display()))?;
+
+        let mut materials = Vec::with_capacity(obj_materials.len());
+        for m in obj_materials {
+            let diffuse_texture = if m.diffuse_texture.is_empty() {
+                Material::solid_color_texture(device, queue, [255, 255, 255, 255])?
+            } else {
+                Self::load_texture(device, queue, parent, &m.diffuse_texture, true)?
+            };
+            let normal_texture = if m.normal_texture.is_empty() {
+                Material::flat_normal_texture(device, queue)?
+            } else {
+                Self::load_texture(device, queue, parent, &m.normal_texture, false)?
+            };
+            materials.push(Material::new(
+                device,
+                &m.name,
+                diffuse_texture,
+                normal_texture,
+                layout,
+            ));
+        }
+        let obj_material_count = materials.len();
+        // Shared by every mesh that has no (valid) material, created on first use
+        let mut default_material = None;
+
+        let mut meshes = Vec::with_capacity(obj_models.len());
+        for m in obj_models {
+            let mut vertices = (0..m.mesh.positions.len() / 3)
+                .map(|i| Vertex {
+                    position: [
+                        m.mesh.positions[i * 3],
+                        m.mesh.positions[i * 3 + 1],
+                        m.mesh.positions[i * 3 + 2],
+                    ],
+                    // OBJ texture coordinates start at the bottom left, wgpu's at the top left
+                    tex_coords: if m.mesh.texcoords.is_empty() {
+                        [0.0, 0.0]
+                    } else {
+                        [m.mesh.texcoords[i * 2], 1.0 - m.mesh.texcoords[i * 2 + 1]]
+                    },
+                    normal: if m.mesh.normals.is_empty() {
+                        [0.0, 0.0, 0.0]
+                    } else {
+                        [
+                            m.mesh.normals[i * 3],
+                            m.mesh.normals[i * 3 + 1],
+                            m.mesh.normals[i * 3 + 2],
+                        ]
+                    },
+                    // Filled in by compute_tangents below
+                    tangent: [0.0, 0.0, 0.0],
+                    bitangent: [0.0, 0.0, 0.0],
+                })
+                .collect::<Vec<_>>();
+            if m.mesh.normals.is_empty() {
+                compute_normals(&mut vertices, &m.mesh.indices);
+            }
+            compute_tangents(&mut vertices, &m.mesh.indices);
+
+            let material = match m.mesh.material_id {
+                Some(id) if id < obj_material_count => id,
+                _ => match default_material {
+                    Some(id) => id,
+                    None => {
+                        let diffuse_texture =
+                            Material::solid_color_texture(device, queue, [255, 255, 255, 255])?;
+                        let normal_texture = Material::flat_normal_texture(device, queue)?;
+                        materials.push(Material::new(
+                            device,
+                            "default",
+                            diffuse_texture,
+                            normal_texture,
+                            layout,
+                        ));
+                        default_material = Some(materials.len() - 1);
+                        materials.len() - 1
+                    }
+                },
+            };
+
+            meshes.push(Mesh::new(
+                device,
+                &m.name,
+                &vertices,
+                &m.mesh.indices,
+                material,
+            ));
+        }
+
+        Ok(Self { meshes, materials })
+    }
+    /// Loads a texture referenced by an MTL file, with mipmaps and anisotropic filtering.
+    pub(crate) fn load_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        parent: &Path,
+        file_name: &str,
+        srgb: bool,
+    ) -> Result<texture::Texture> {
+        let texture_path = parent.join(file_name);
+        let bytes = std::fs::read(&texture_path)
+            .with_context(|| format!("Failed to read texture {}", texture_path.display()))?;
+        texture::Texture::from_bytes_with_options(
+            device,
+            queue,
+            &bytes,
+            file_name,
+            texture::TextureOptions {
+                generate_mipmaps: true,
+                filtering: texture::Filtering::Anisotropic(16),
+                srgb,
+            },
+        )
+    }
+}
+
+/// Fills in smooth vertex normals by averaging the normals of the faces around each vertex.
+pub(crate) fn compute_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    use cgmath::prelude::*;
+
+    let mut normals = vec![cgmath::Vector3::zero(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+        let p0 = cgmath::Vector3::from(vertices[a].position);
+        let p1 = cgmath::Vector3::from(vertices[b].position);
+        let p2 = cgmath::Vector3::from(vertices[c].position);
+        // Not normalized, so larger faces contribute more
+        let face_normal = (p1 - p0).cross(p2 - p0);
+        for i in [a, b, c] {
+            normals[i] += face_normal;
+        }
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        vertex.normal = if normal.is_zero() {
+            [0.0, 0.0, 1.0]
+        } else {
+            normal.normalize().into()
+        };
+    }
+}
+
+/// Fills in per-vertex tangents and bitangents from the texture coordinates, for normal mapping.
+/// The tangent points along +u and the bitangent up the texture (along -v, as wgpu's texture
+/// coordinates start at the top left), both orthogonal to the vertex normal.
+pub(crate) fn compute_tangents<I: Copy + Into<u32>>(vertices: &mut [Vertex], indices: &[I]) {
+    use cgmath::prelude::*;
+
+    let mut tangents = vec![cgmath::Vector3::zero(); vertices.len()];
+    let mut bitangents = vec![cgmath::Vector3::zero(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i.into() as usize);
+        let p0 = cgmath::Vector3::from(vertices[a].position);
+        let p1 = cgmath::Vector3::from(vertices[b].position);
+        let p2 = cgmath::Vector3::from(vertices[c].position);
+        let uv0 = cgmath::Vector2::from(vertices[a].tex_coords);
+        let uv1 = cgmath::Vector2::from(vertices[b].tex_coords);
+        let uv2 = cgmath::Vector2::from(vertices[c].tex_coords);
+
+        let (dp1, dp2) = (p1 - p0, p2 - p0);
+        let (duv1, duv2) = (uv1 - uv0, uv2 - uv0);
+        let determinant = duv1.x * duv2.y - duv1.y * duv2.x;
+        // Skip triangles whose texture coordinates are degenerate
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / determinant;
+        let tangent = (dp1 * duv2.y - dp2 * duv1.y) * r;
+        let bitangent = -(dp2 * duv1.x - dp1 * duv2.x) * r;
+        for i in [a, b, c] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+    for ((vertex, tangent), bitangent) in vertices.iter_mut().zip(tangents).zip(bitangents) {
+        let normal = cgmath::Vector3::from(vertex.normal);
+        // Gram-Schmidt, falling back to an arbitrary perpendicular axis if there's no tangent
+        let mut tangent = tangent - normal * normal.dot(tangent);
+        if tangent.magnitude2() < f32::EPSILON {
+            let axis = if normal.x.abs() < 0.9 {
+                cgmath::Vector3::unit_x()
+            } else {
+                cgmath::Vector3::unit_y()
+            };
+            tangent = axis - normal * normal.dot(axis);
+        }
+        let tangent = tangent.normalize();
+        let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        vertex.tangent = tangent.into();
+        vertex.bitangent = (normal.cross(tangent) * handedness).into();
+    }
+}
+
+pub trait DrawModel<'a> {
+    fn draw_mesh_instanced(
+        &mut self,
+        mesh: &'a Mesh,
+        material: &'a Material,
+        instances: Range<u32>,
+    );
+    fn draw_model_instanced(&mut self, model: &'a Model, instances: Range<u32>);
+    /// Draws only the geometry, for passes that don't bind materials (e.g. shadows).
+    fn draw_mesh_geometry_instanced(&mut self, mesh: &'a Mesh, instances: Range<u32>);
+    /// Draws `mesh` with the `DrawIndexedIndirect` arguments at `indirect_offset`.
+    fn draw_mesh_indirect(
+        &mut self,
+        mesh: &'a Mesh,
+        material: &'a Material,
+        indirect_buffer: &'a wgpu::Buffer,
+        indirect_offset: wgpu::BufferAddress,
+    );
+}
+impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_mesh_instanced(
+        &mut self,
+        mesh: &'b Mesh,
+        material: &'b Material,
+        instances: Range<u32>,
+    ) {
+        self.set_bind_group(0, &material.bind_group, &[]);
+        self.draw_mesh_geometry_instanced(mesh, instances);
+    }
+    fn draw_model_instanced(&mut self, model: &'b Model, instances: Range<u32>) {
+        for mesh in &model.meshes {
+            let material = &model.materials[mesh.material];
+            self.draw_mesh_instanced(mesh, material, instances.clone());
+        }
+    }
+    fn draw_mesh_geometry_instanced(&mut self, mesh: &'b Mesh, instances: Range<u32>) {
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.draw_indexed(0..mesh.num_elements, 0, instances);
+    }
+    fn draw_mesh_indirect(
+        &mut self,
+        mesh: &'b Mesh,
+        material: &'b Material,
+        indirect_buffer: &'b wgpu::Buffer,
+        indirect_offset: wgpu::BufferAddress,
+    ) {
+        self.set_bind_group(0, &material.bind_group, &[]);
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.draw_indexed_indirect(indirect_buffer, indirect_offset);
+    }
+}
@@ -0,0 +1,796 @@
+use wgpu::util::DeviceExt;
+
+use crate::render_graph::{PassContext, RenderGraph, ResourceId, TextureDesc, TextureSize};
+use crate::{hdr, pipeline, texture};
+
+/// How many times the bloom blur halves the resolution, starting at half the scene's size.
+const BLOOM_LEVELS: u32 = 5;
+
+/// The fullscreen triangle's `vs_main` and the sRGB conversions, shared by the tonemap, bloom,
+/// post-processing and cubemap conversion shaders.
+pub const FULLSCREEN_PRELUDE: &str = include_str!("fullscreen.wgsl");
+
+/// `source` with [`FULLSCREEN_PRELUDE`] in front, as the fullscreen passes are built from.
+pub fn fullscreen_shader(source: &str) -> String {
+    pipeline::with_prelude(FULLSCREEN_PRELUDE, source)
+}
+
+/// Adds a blurred glow around the parts of the HDR scene brighter than `threshold`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    /// Brightness (the largest channel, before exposure) above which pixels glow.
+    pub threshold: f32,
+    /// Width of the soft transition below `threshold`, as a fraction of it.
+    pub knee: f32,
+    /// How much of the blurred highlights is added back to the scene.
+    pub intensity: f32,
+}
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.3,
+        }
+    }
+}
+
+/// Looks up every color in a 3D table, see [`texture::Texture::lut_from_bytes`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorGradingSettings {
+    pub enabled: bool,
+    /// Blends between the original colors (0) and the table's (1).
+    pub strength: f32,
+}
+impl Default for ColorGradingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            strength: 1.0,
+        }
+    }
+}
+
+/// Darkens the frame towards its corners.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VignetteSettings {
+    pub enabled: bool,
+    /// How dark the corners get, from 0 to 1.
+    pub intensity: f32,
+    /// Distance from the center where the darkening starts, 1 being the corners.
+    pub radius: f32,
+    /// Distance over which the darkening fades in.
+    pub smoothness: f32,
+}
+impl Default for VignetteSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 0.5,
+            radius: 0.5,
+            smoothness: 0.5,
+        }
+    }
+}
+
+/// Fast approximate anti-aliasing, which blurs along the edges it finds in the final image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FxaaSettings {
+    pub enabled: bool,
+    /// Longest distance in pixels the blur along an edge reaches.
+    pub span_max: f32,
+    /// How much the blur shrinks in bright areas.
+    pub reduce_mul: f32,
+    /// Smallest amount the blur shrinks by.
+    pub reduce_min: f32,
+}
+impl Default for FxaaSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            span_max: 8.0,
+            reduce_mul: 1.0 / 8.0,
+            reduce_min: 1.0 / 128.0,
+        }
+    }
+}
+
+/// The effects applied after the scene is drawn, in the order they run. Bloom works on the
+/// HDR scene before tonemapping, the others on the tonemapped frame. All are off by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PostSettings {
+    pub bloom: BloomSettings,
+    pub color_grading: ColorGradingSettings,
+    pub vignette: VignetteSettings,
+    pub fxaa: FxaaSettings,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    apply_threshold: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColorGradingUniform {
+    strength: f32,
+    srgb_surface: u32,
+    _padding: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct VignetteUniform {
+    intensity: f32,
+    radius: f32,
+    smoothness: f32,
+    srgb_surface: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct FxaaUniform {
+    span_max: f32,
+    reduce_mul: f32,
+    reduce_min: f32,
+    srgb_surface: u32,
+}
+
+/// The textures of the passes, which the render graph allocates. Declared by
+/// [`PostProcess::add_textures`].
+#[derive(Clone, Debug)]
+pub(crate) struct PostTextures {
+    /// The HDR scene, which bloom reads.
+    pub scene: ResourceId,
+    /// The scene with the bloom added, which tonemapping reads instead while bloom is on.
+    pub bloomed: Option<ResourceId>,
+    /// Half the scene's size and smaller from there, while bloom is on.
+    pub bloom_levels: Vec<ResourceId>,
+    /// Two surface-format textures the passes after tonemapping ping-pong between.
+    pub ldr: [ResourceId; 2],
+}
+
+/// The bind groups reading the graph's textures, made again whenever it reallocates them.
+struct BindGroups {
+    /// Thresholds the scene into the first bloom level.
+    prefilter: Option<wgpu::BindGroup>,
+    /// `downsample[i]` reads level `i` to draw level `i + 1`.
+    downsample: Vec<wgpu::BindGroup>,
+    /// `upsample[i]` reads level `i + 1` to add onto level `i`.
+    upsample: Vec<wgpu::BindGroup>,
+    /// Reads the first level to add onto the scene.
+    composite: Option<wgpu::BindGroup>,
+    /// Per pass, the bind groups reading each of the `ldr` targets.
+    color_grading: [wgpu::BindGroup; 2],
+    vignette: [wgpu::BindGroup; 2],
+    fxaa: [wgpu::BindGroup; 2],
+}
+impl BindGroups {
+    /// # Panics
+    ///
+    /// If the graph wasn't compiled since `textures` were added to it.
+    fn new<C>(
+        device: &wgpu::Device,
+        graph: &RenderGraph<C>,
+        textures: &PostTextures,
+        resources: &Resources,
+    ) -> Self {
+        let view = |id: ResourceId| {
+            graph
+                .texture(id)
+                .expect("post-processing textures are allocated by the graph")
+        };
+        let buffers = &resources.buffers;
+        let bind_group = |id: ResourceId, buffer: &wgpu::Buffer| {
+            resources.bind_group(device, view(id), buffer, false)
+        };
+        let levels = &textures.bloom_levels;
+        let ldr = textures.ldr.map(view);
+        let ldr_bind_groups = |buffer: &wgpu::Buffer, with_lut: bool| {
+            ldr.map(|view| resources.bind_group(device, view, buffer, with_lut))
+        };
+
+        Self {
+            prefilter: textures
+                .bloomed
+                .map(|_| bind_group(textures.scene, &buffers.prefilter)),
+            downsample: levels[..levels.len().saturating_sub(1)]
+                .iter()
+                .map(|&level| bind_group(level, &buffers.downsample))
+                .collect(),
+            upsample: levels
+                .iter()
+                .skip(1)
+                .map(|&level| bind_group(level, &buffers.upsample))
+                .collect(),
+            composite: levels
+                .first()
+                .map(|&level| bind_group(level, &buffers.composite)),
+            color_grading: ldr_bind_groups(&buffers.color_grading, true),
+            vignette: ldr_bind_groups(&buffers.vignette, false),
+            fxaa: ldr_bind_groups(&buffers.fxaa, false),
+        }
+    }
+}
+
+/// The uniform buffers of the passes, rewritten when the settings change.
+struct Buffers {
+    prefilter: wgpu::Buffer,
+    downsample: wgpu::Buffer,
+    upsample: wgpu::Buffer,
+    composite: wgpu::Buffer,
+    color_grading: wgpu::Buffer,
+    vignette: wgpu::Buffer,
+    fxaa: wgpu::Buffer,
+}
+
+/// The resources the bind groups are made of, other than the graph's textures.
+struct Resources {
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    color_grading_layout: wgpu::BindGroupLayout,
+    buffers: Buffers,
+    lut: texture::Texture,
+}
+impl Resources {
+    fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        view: &wgpu::TextureView,
+        buffer: &wgpu::Buffer,
+        with_lut: bool,
+    ) -> wgpu::BindGroup {
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: buffer.as_entire_binding(),
+            },
+        ];
+        let layout = if with_lut {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&self.lut.view),
+            });
+            &self.color_grading_layout
+        } else {
+            &self.bind_group_layout
+        };
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &entries,
+            label: Some("post_bind_group"),
+        })
+    }
+}
+
+/// Chain of fullscreen passes over the HDR scene texture: bloom before tonemapping, then
+/// color grading, vignette and FXAA between the tonemapped frame and the surface.
+pub(crate) struct PostProcess {
+    settings: PostSettings,
+    /// Whether the surface format is sRGB, so the passes after tonemapping read and write
+    /// linear values but work on encoded ones.
+    srgb_surface: bool,
+    resources: Resources,
+    /// Set by `bind`.
+    bind_groups: Option<BindGroups>,
+    bloom_downsample: pipeline::PipelineId,
+    bloom_upsample: pipeline::PipelineId,
+    color_grading: pipeline::PipelineId,
+    vignette: pipeline::PipelineId,
+    fxaa: pipeline::PipelineId,
+}
+impl PostProcess {
+    /// Sets up the passes, with `lut` for color grading. Their textures are added to the
+    /// render graph with [`Self::add_textures`].
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        pipelines: &mut pipeline::PipelineCache,
+        lut: texture::Texture,
+        settings: PostSettings,
+    ) -> Self {
+        let srgb_surface = config.format.describe().srgb;
+        let uniform_buffer = |label: &str, contents: &[u8]| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            })
+        };
+        let buffers = Buffers {
+            prefilter: uniform_buffer(
+                "Bloom Prefilter Buffer",
+                bytemuck::bytes_of(&Self::prefilter_uniform(&settings)),
+            ),
+            downsample: uniform_buffer(
+                "Bloom Downsample Buffer",
+                bytemuck::bytes_of(&BloomUniform {
+                    threshold: 0.0,
+                    knee: 0.0,
+                    intensity: 1.0,
+                    apply_threshold: 0,
+                }),
+            ),
+            upsample: uniform_buffer(
+                "Bloom Upsample Buffer",
+                bytemuck::bytes_of(&BloomUniform {
+                    threshold: 0.0,
+                    knee: 0.0,
+                    intensity: 1.0,
+                    apply_threshold: 0,
+                }),
+            ),
+            composite: uniform_buffer(
+                "Bloom Composite Buffer",
+                bytemuck::bytes_of(&Self::composite_uniform(&settings)),
+            ),
+            color_grading: uniform_buffer(
+                "Color Grading Buffer",
+                bytemuck::bytes_of(&Self::color_grading_uniform(&settings, srgb_surface)),
+            ),
+            vignette: uniform_buffer(
+                "Vignette Buffer",
+                bytemuck::bytes_of(&Self::vignette_uniform(&settings, srgb_surface)),
+            ),
+            fxaa: uniform_buffer(
+                "FXAA Buffer",
+                bytemuck::bytes_of(&Self::fxaa_uniform(&settings, srgb_surface)),
+            ),
+        };
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let mut entries = vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &entries,
+            label: Some("post_bind_group_layout"),
+        });
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 3,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D3,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        });
+        let color_grading_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &entries,
+                label: Some("color_grading_bind_group_layout"),
+            });
+        for (name, layout) in [
+            ("post", &bind_group_layout),
+            ("color_grading", &color_grading_layout),
+        ] {
+            pipelines.add_layout(
+                name,
+                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Post Pipeline Layout"),
+                    bind_group_layouts: &[layout],
+                    push_constant_ranges: &[],
+                }),
+            );
+        }
+
+        let bloom_downsample = pipelines.get_or_create(
+            device,
+            &pipeline::PipelineDesc::new(
+                "Bloom Downsample Pipeline",
+                fullscreen_shader(include_str!("bloom_downsample.wgsl")),
+                "post",
+                hdr::HDR_FORMAT,
+            )
+            .cull_mode(None),
+        );
+        let bloom_upsample = pipelines.get_or_create(
+            device,
+            &pipeline::PipelineDesc::new(
+                "Bloom Upsample Pipeline",
+                fullscreen_shader(include_str!("bloom_upsample.wgsl")),
+                "post",
+                hdr::HDR_FORMAT,
+            )
+            // Adds onto the larger level, keeping its alpha
+            .blend(Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            }))
+            .cull_mode(None),
+        );
+        let color_grading = pipelines.get_or_create(
+            device,
+            &pipeline::PipelineDesc::new(
+                "Color Grading Pipeline",
+                fullscreen_shader(include_str!("color_grading.wgsl")),
+                "color_grading",
+                config.format,
+            )
+            .cull_mode(None),
+        );
+        let vignette = pipelines.get_or_create(
+            device,
+            &pipeline::PipelineDesc::new(
+                "Vignette Pipeline",
+                fullscreen_shader(include_str!("vignette.wgsl")),
+                "post",
+                config.format,
+            )
+            .cull_mode(None),
+        );
+        let fxaa = pipelines.get_or_create(
+            device,
+            &pipeline::PipelineDesc::new(
+                "FXAA Pipeline",
+                fullscreen_shader(include_str!("fxaa.wgsl")),
+                "post",
+                config.format,
+            )
+            .cull_mode(None),
+        );
+
+        let resources = Resources {
+            sampler,
+            bind_group_layout,
+            color_grading_layout,
+            buffers,
+            lut,
+        };
+        Self {
+            settings,
+            srgb_surface,
+            resources,
+            bind_groups: None,
+            bloom_downsample,
+            bloom_upsample,
+            color_grading,
+            vignette,
+            fxaa,
+        }
+    }
+    pub fn settings(&self) -> PostSettings {
+        self.settings
+    }
+    pub fn set_settings(&mut self, queue: &wgpu::Queue, settings: PostSettings) {
+        self.settings = settings;
+        let buffers = &self.resources.buffers;
+        queue.write_buffer(
+            &buffers.prefilter,
+            0,
+            bytemuck::bytes_of(&Self::prefilter_uniform(&settings)),
+        );
+        queue.write_buffer(
+            &buffers.composite,
+            0,
+            bytemuck::bytes_of(&Self::composite_uniform(&settings)),
+        );
+        queue.write_buffer(
+            &buffers.color_grading,
+            0,
+            bytemuck::bytes_of(&Self::color_grading_uniform(&settings, self.srgb_surface)),
+        );
+        queue.write_buffer(
+            &buffers.vignette,
+            0,
+            bytemuck::bytes_of(&Self::vignette_uniform(&settings, self.srgb_surface)),
+        );
+        queue.write_buffer(
+            &buffers.fxaa,
+            0,
+            bytemuck::bytes_of(&Self::fxaa_uniform(&settings, self.srgb_surface)),
+        );
+    }
+    /// Declares the textures of the passes in `graph`, for a surface of `format` and the HDR
+    /// `scene`. The bloom textures are only added while bloom is enabled.
+    pub fn add_textures<C>(
+        &self,
+        graph: &mut RenderGraph<C>,
+        scene: ResourceId,
+        format: wgpu::TextureFormat,
+    ) -> PostTextures {
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+        let bloom = self.settings.bloom.enabled;
+        let bloomed = bloom.then(|| {
+            graph.add_texture(TextureDesc::new(
+                "bloomed_scene",
+                hdr::HDR_FORMAT,
+                usage | wgpu::TextureUsages::COPY_DST,
+            ))
+        });
+        let bloom_levels = (1..=BLOOM_LEVELS)
+            .filter(|_| bloom)
+            .map(|level| {
+                graph.add_texture(
+                    TextureDesc::new("bloom_level", hdr::HDR_FORMAT, usage)
+                        .size(TextureSize::Downscaled(level)),
+                )
+            })
+            .collect();
+        let ldr = [
+            graph.add_texture(TextureDesc::new("post_target", format, usage)),
+            graph.add_texture(TextureDesc::new("post_target", format, usage)),
+        ];
+        PostTextures {
+            scene,
+            bloomed,
+            bloom_levels,
+            ldr,
+        }
+    }
+    /// Makes the bind groups reading `textures` from `graph`, which replaces them when it's
+    /// compiled or resized, so this has to be called again after that.
+    pub fn bind<C>(
+        &mut self,
+        device: &wgpu::Device,
+        graph: &RenderGraph<C>,
+        textures: &PostTextures,
+    ) {
+        self.bind_groups = Some(BindGroups::new(device, graph, textures, &self.resources));
+    }
+    /// Replaces the color-grading lookup table, which the passes read once bound again with
+    /// [`Self::bind`].
+    pub fn set_lut(&mut self, lut: texture::Texture) {
+        self.resources.lut = lut;
+    }
+    /// Adds the bloom onto a copy of the HDR scene, `textures.bloomed`. Only declared while
+    /// bloom is enabled, see [`Self::add_textures`].
+    pub fn draw_bloom(
+        &self,
+        pass: &mut PassContext,
+        pipelines: &pipeline::PipelineCache,
+        textures: &PostTextures,
+    ) {
+        let bind_groups = self.bind_groups();
+        let (Some(bloomed), Some(prefilter), Some(composite)) = (
+            textures.bloomed,
+            &bind_groups.prefilter,
+            &bind_groups.composite,
+        ) else {
+            return;
+        };
+        pass.copy_texture(textures.scene, bloomed);
+
+        let levels = textures
+            .bloom_levels
+            .iter()
+            .map(|&level| pass.texture(level))
+            .collect::<Vec<_>>();
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+        let downsample = &pipelines[self.bloom_downsample];
+        let upsample = &pipelines[self.bloom_upsample];
+
+        fullscreen_pass(
+            pass.encoder,
+            "Bloom Prefilter Pass",
+            downsample,
+            prefilter,
+            levels[0],
+            clear,
+        );
+        for (bind_group, level) in bind_groups.downsample.iter().zip(&levels[1..]) {
+            fullscreen_pass(
+                pass.encoder,
+                "Bloom Downsample Pass",
+                downsample,
+                bind_group,
+                level,
+                clear,
+            );
+        }
+        for (bind_group, level) in bind_groups.upsample.iter().zip(&levels).rev() {
+            fullscreen_pass(
+                pass.encoder,
+                "Bloom Upsample Pass",
+                upsample,
+                bind_group,
+                level,
+                wgpu::LoadOp::Load,
+            );
+        }
+        fullscreen_pass(
+            pass.encoder,
+            "Bloom Composite Pass",
+            upsample,
+            composite,
+            pass.texture(bloomed),
+            wgpu::LoadOp::Load,
+        );
+    }
+    /// Where tonemapping should draw: the first of `textures.ldr` if any pass after it is
+    /// enabled, otherwise straight into the frame.
+    pub fn tonemap_target<'a>(
+        &self,
+        pass: &PassContext<'a>,
+        textures: &PostTextures,
+    ) -> &'a wgpu::TextureView {
+        if self.ldr_passes().next().is_some() {
+            pass.texture(textures.ldr[0])
+        } else {
+            pass.output
+        }
+    }
+    /// Runs the enabled passes after tonemapping, the last one drawing into the frame.
+    pub fn draw_ldr(
+        &self,
+        pass: &mut PassContext,
+        pipelines: &pipeline::PipelineCache,
+        textures: &PostTextures,
+    ) {
+        let passes = self.ldr_passes().collect::<Vec<_>>();
+        for (i, (label, pipeline, bind_groups)) in passes.iter().enumerate() {
+            // Tonemapping drew into the first target
+            let input = i % 2;
+            let output = if i + 1 == passes.len() {
+                pass.output
+            } else {
+                pass.texture(textures.ldr[1 - input])
+            };
+            fullscreen_pass(
+                pass.encoder,
+                label,
+                &pipelines[*pipeline],
+                &bind_groups[input],
+                output,
+                // Every pixel is overwritten
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            );
+        }
+    }
+    /// # Panics
+    ///
+    /// If the passes run before [`Self::bind`].
+    fn bind_groups(&self) -> &BindGroups {
+        self.bind_groups
+            .as_ref()
+            .expect("PostProcess draws before bind")
+    }
+    /// The enabled passes after tonemapping, in order.
+    fn ldr_passes(
+        &self,
+    ) -> impl Iterator<Item = (&'static str, pipeline::PipelineId, &[wgpu::BindGroup; 2])> {
+        let settings = &self.settings;
+        let bind_groups = self.bind_groups();
+        [
+            (
+                settings.color_grading.enabled,
+                "Color Grading Pass",
+                self.color_grading,
+                &bind_groups.color_grading,
+            ),
+            (
+                settings.vignette.enabled,
+                "Vignette Pass",
+                self.vignette,
+                &bind_groups.vignette,
+            ),
+            (
+                settings.fxaa.enabled,
+                "FXAA Pass",
+                self.fxaa,
+                &bind_groups.fxaa,
+            ),
+        ]
+        .into_iter()
+        .filter(|(enabled, ..)| *enabled)
+        .map(|(_, label, pipeline, bind_groups)| (label, pipeline, bind_groups))
+    }
+    fn prefilter_uniform(settings: &PostSettings) -> BloomUniform {
+        BloomUniform {
+            threshold: settings.bloom.threshold,
+            knee: settings.bloom.knee,
+            intensity: 1.0,
+            apply_threshold: 1,
+        }
+    }
+    fn composite_uniform(settings: &PostSettings) -> BloomUniform {
+        BloomUniform {
+            threshold: 0.0,
+            knee: 0.0,
+            intensity: settings.bloom.intensity,
+            apply_threshold: 0,
+        }
+    }
+    fn color_grading_uniform(settings: &PostSettings, srgb_surface: bool) -> ColorGradingUniform {
+        ColorGradingUniform {
+            strength: settings.color_grading.strength,
+            srgb_surface: srgb_surface as u32,
+            _padding: [0; 2],
+        }
+    }
+    fn vignette_uniform(settings: &PostSettings, srgb_surface: bool) -> VignetteUniform {
+        VignetteUniform {
+            intensity: settings.vignette.intensity,
+            radius: settings.vignette.radius,
+            smoothness: settings.vignette.smoothness,
+            srgb_surface: srgb_surface as u32,
+        }
+    }
+    fn fxaa_uniform(settings: &PostSettings, srgb_surface: bool) -> FxaaUniform {
+        FxaaUniform {
+            span_max: settings.fxaa.span_max,
+            reduce_mul: settings.fxaa.reduce_mul,
+            reduce_min: settings.fxaa.reduce_min,
+            srgb_surface: srgb_surface as u32,
+        }
+    }
+}
+
+fn fullscreen_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    view: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations { load, store: true },
+        })],
+        depth_stencil_attachment: None,
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
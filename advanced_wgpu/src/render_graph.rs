@@ -0,0 +1,421 @@
+use std::ops::Range;
+
+use thiserror::Error;
+
+/// Handle to a texture or buffer declared in a [`RenderGraph`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+/// Handle to a pass added to a [`RenderGraph`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PassId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureSize {
+    /// The size the graph was compiled or last resized with.
+    Surface,
+    Fixed {
+        width: u32,
+        height: u32,
+    },
+    /// The surface size halved this many times, rounded down.
+    Downscaled(u32),
+}
+
+/// A texture the graph allocates, only alive between the first and last pass using it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureDesc {
+    pub label: &'static str,
+    pub size: TextureSize,
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub usage: wgpu::TextureUsages,
+}
+impl TextureDesc {
+    /// A single-sampled texture of the surface's size. The other methods change one
+    /// setting each.
+    pub fn new(
+        label: &'static str,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> Self {
+        Self {
+            label,
+            size: TextureSize::Surface,
+            format,
+            sample_count: 1,
+            usage,
+        }
+    }
+    pub fn size(mut self, size: TextureSize) -> Self {
+        self.size = size;
+        self
+    }
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+}
+
+/// A pass's name and the resources it reads and writes, which decide where it runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PassDesc {
+    pub name: &'static str,
+    pub reads: Vec<ResourceId>,
+    pub writes: Vec<ResourceId>,
+}
+impl PassDesc {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            reads: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+    pub fn read(mut self, resource: ResourceId) -> Self {
+        self.reads.push(resource);
+        self
+    }
+    pub fn write(mut self, resource: ResourceId) -> Self {
+        self.writes.push(resource);
+        self
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum GraphError {
+    #[error("passes {0:?} depend on each other")]
+    Cycle(Vec<&'static str>),
+    #[error("pass {0} uses a resource that isn't from this graph")]
+    UnknownResource(&'static str),
+}
+
+/// What a pass gets to record its commands with.
+pub struct PassContext<'a> {
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    /// The view given to [`RenderGraph::execute`], usually the frame.
+    pub output: &'a wgpu::TextureView,
+    allocation: &'a [Option<usize>],
+    textures: &'a [PhysicalTexture],
+}
+impl<'a> PassContext<'a> {
+    /// The view of a texture added with [`RenderGraph::add_texture`].
+    ///
+    /// # Panics
+    ///
+    /// If `id` is imported rather than added, or no pass uses it.
+    pub fn texture(&self, id: ResourceId) -> &'a wgpu::TextureView {
+        let textures = self.textures;
+        match self.allocation.get(id.0) {
+            Some(Some(physical)) => &textures[*physical].view,
+            _ => panic!("{:?} isn't a texture allocated by the graph", id),
+        }
+    }
+    /// Copies all of `source` into `destination`, both added with
+    /// [`RenderGraph::add_texture`] with the same size and format, and `COPY_SRC` and
+    /// `COPY_DST` usage.
+    pub fn copy_texture(&mut self, source: ResourceId, destination: ResourceId) {
+        let (allocation, textures) = (self.allocation, self.textures);
+        let physical = |id: ResourceId| match allocation.get(id.0) {
+            Some(Some(physical)) => &textures[*physical],
+            _ => panic!("{:?} isn't a texture allocated by the graph", id),
+        };
+        let (source, destination) = (physical(source), physical(destination));
+        self.encoder.copy_texture_to_texture(
+            source.texture.as_image_copy(),
+            destination.texture.as_image_copy(),
+            source.desc.size,
+        );
+    }
+}
+
+type RunPass<C> = Box<dyn Fn(&C, &mut PassContext)>;
+
+struct Pass<C> {
+    desc: PassDesc,
+    run: RunPass<C>,
+}
+
+enum Resource {
+    /// Owned outside the graph, declared only so passes can depend on each other through it.
+    Imported,
+    Transient(TextureDesc),
+}
+
+struct PhysicalTexture {
+    desc: wgpu::TextureDescriptor<'static>,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+/// Orders passes by the resources they read and write, and allocates the textures that only
+/// live for part of the frame, sharing one texture between those whose lifetimes don't
+/// overlap. Passes run with a shared `&C`, which owns everything imported.
+///
+/// A pass runs after every pass writing a resource it reads. Passes writing the same
+/// resource run in the order they were added, and a pass that also reads what it writes
+/// only waits for the writers added before it. Passes without dependencies between them
+/// keep the order they were added in.
+pub struct RenderGraph<C> {
+    resources: Vec<Resource>,
+    passes: Vec<Pass<C>>,
+    /// Set by `compile`.
+    order: Option<Vec<PassId>>,
+    /// Per resource, the positions in `order` of the first and last pass using it.
+    lifetimes: Vec<Option<Range<usize>>>,
+    /// Per resource, its index in `textures` if the graph allocated it.
+    allocation: Vec<Option<usize>>,
+    textures: Vec<PhysicalTexture>,
+    size: (u32, u32),
+}
+impl<C> Default for RenderGraph<C> {
+    fn default() -> Self {
+        Self {
+            resources: Vec::new(),
+            passes: Vec::new(),
+            order: None,
+            lifetimes: Vec::new(),
+            allocation: Vec::new(),
+            textures: Vec::new(),
+            size: (0, 0),
+        }
+    }
+}
+impl<C> RenderGraph<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Declares a texture or buffer owned outside the graph, e.g. the frame or a shadow map.
+    pub fn import(&mut self) -> ResourceId {
+        self.resources.push(Resource::Imported);
+        ResourceId(self.resources.len() - 1)
+    }
+    /// Declares a texture for the graph to allocate, see [`PassContext::texture`].
+    pub fn add_texture(&mut self, desc: TextureDesc) -> ResourceId {
+        self.resources.push(Resource::Transient(desc));
+        ResourceId(self.resources.len() - 1)
+    }
+    /// Adds a pass that records its commands with `run`. Changes to the graph take effect
+    /// with the next [`Self::compile`].
+    pub fn add_pass(
+        &mut self,
+        desc: PassDesc,
+        run: impl Fn(&C, &mut PassContext) + 'static,
+    ) -> PassId {
+        self.passes.push(Pass {
+            desc,
+            run: Box::new(run),
+        });
+        self.order = None;
+        PassId(self.passes.len() - 1)
+    }
+    /// Orders the passes and allocates the graph's textures for a surface of `width` x
+    /// `height`.
+    pub fn compile(
+        &mut self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> Result<(), GraphError> {
+        let order = self.sort()?;
+
+        let mut lifetimes = vec![None::<Range<usize>>; self.resources.len()];
+        for (position, id) in order.iter().enumerate() {
+            let desc = &self.passes[id.0].desc;
+            for resource in desc.reads.iter().chain(&desc.writes) {
+                let lifetime = lifetimes[resource.0].get_or_insert(position..position + 1);
+                lifetime.end = position + 1;
+            }
+        }
+
+        self.order = Some(order);
+        self.lifetimes = lifetimes;
+        self.size = (width, height);
+        self.allocate(device);
+        Ok(())
+    }
+    /// Recreates the graph's textures for a new surface size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if (width, height) != self.size {
+            self.size = (width, height);
+            self.allocate(device);
+        }
+    }
+    /// Runs the passes in order, recording into `encoder`.
+    ///
+    /// # Panics
+    ///
+    /// If passes were added since the last [`Self::compile`].
+    pub fn execute(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        context: &C,
+    ) {
+        let order = self
+            .order
+            .as_ref()
+            .expect("RenderGraph::execute before compile");
+        for id in order {
+            let mut pass_context = PassContext {
+                encoder: &mut *encoder,
+                output,
+                allocation: &self.allocation,
+                textures: &self.textures,
+            };
+            (self.passes[id.0].run)(context, &mut pass_context);
+        }
+    }
+    /// The names of the passes in the order they run, once compiled.
+    pub fn execution_order(&self) -> Vec<&'static str> {
+        self.order
+            .iter()
+            .flatten()
+            .map(|id| self.passes[id.0].desc.name)
+            .collect()
+    }
+    /// The positions in [`Self::execution_order`] of the first pass using `resource` and
+    /// one past the last, or `None` if no pass uses it.
+    pub fn lifetime(&self, resource: ResourceId) -> Option<Range<usize>> {
+        self.lifetimes.get(resource.0).cloned().flatten()
+    }
+    /// Whether `a` and `b` are the same texture underneath.
+    pub fn aliases(&self, a: ResourceId, b: ResourceId) -> bool {
+        let physical = |id: ResourceId| self.allocation.get(id.0).copied().flatten();
+        physical(a).is_some() && physical(a) == physical(b)
+    }
+    /// The view of a texture the graph allocated, e.g. for a bind group. It's replaced by
+    /// [`Self::compile`] and [`Self::resize`], so anything made from it has to be too.
+    pub fn texture(&self, resource: ResourceId) -> Option<&wgpu::TextureView> {
+        let physical = self.allocation.get(resource.0).copied().flatten()?;
+        Some(&self.textures[physical].view)
+    }
+    /// The size of a texture the graph allocated.
+    pub fn texture_size(&self, resource: ResourceId) -> Option<wgpu::Extent3d> {
+        let physical = self.allocation.get(resource.0).copied().flatten()?;
+        Some(self.textures[physical].desc.size)
+    }
+    /// The number of textures the graph allocated, fewer than it declares when some alias.
+    pub fn texture_count(&self) -> usize {
+        self.textures.len()
+    }
+    /// Topological order of the passes, taking the earliest added among those ready.
+    fn sort(&self) -> Result<Vec<PassId>, GraphError> {
+        for pass in &self.passes {
+            let desc = &pass.desc;
+            if desc
+                .reads
+                .iter()
+                .chain(&desc.writes)
+                .any(|id| id.0 >= self.resources.len())
+            {
+                return Err(GraphError::UnknownResource(desc.name));
+            }
+        }
+
+        let mut dependencies = vec![Vec::new(); self.passes.len()];
+        for resource in 0..self.resources.len() {
+            let uses = |ids: &[ResourceId]| ids.iter().any(|id| id.0 == resource);
+            let writers = (0..self.passes.len())
+                .filter(|&i| uses(&self.passes[i].desc.writes))
+                .collect::<Vec<_>>();
+            for pair in writers.windows(2) {
+                dependencies[pair[1]].push(pair[0]);
+            }
+            for (i, pass) in self.passes.iter().enumerate() {
+                if uses(&pass.desc.reads) && !writers.contains(&i) {
+                    dependencies[i].extend(&writers);
+                }
+            }
+        }
+
+        let mut done = vec![false; self.passes.len()];
+        let mut order = Vec::with_capacity(self.passes.len());
+        while order.len() < self.passes.len() {
+            let ready = (0..self.passes.len())
+                .find(|&i| !done[i] && dependencies[i].iter().all(|&dependency| done[dependency]));
+            let Some(ready) = ready else {
+                let stuck = (0..self.passes.len())
+                    .filter(|&i| !done[i])
+                    .map(|i| self.passes[i].desc.name)
+                    .collect();
+                return Err(GraphError::Cycle(stuck));
+            };
+            done[ready] = true;
+            order.push(PassId(ready));
+        }
+        Ok(order)
+    }
+    /// (Re)creates the textures for the current size, reusing one for textures of the same
+    /// description as long as their lifetimes don't overlap.
+    fn allocate(&mut self, device: &wgpu::Device) {
+        let mut transients = self
+            .resources
+            .iter()
+            .enumerate()
+            .filter_map(|(i, resource)| match resource {
+                Resource::Transient(desc) => Some((i, *desc, self.lifetimes[i].clone()?)),
+                Resource::Imported => None,
+            })
+            .collect::<Vec<_>>();
+        transients.sort_by_key(|(_, _, lifetime)| lifetime.start);
+
+        self.allocation = vec![None; self.resources.len()];
+        self.textures.clear();
+        // Per texture, the position after its last use so far
+        let mut free_from = Vec::new();
+        for (resource, desc, lifetime) in transients {
+            let descriptor = self.descriptor(&desc);
+            let reusable = self
+                .textures
+                .iter()
+                .zip(&free_from)
+                .position(|(texture, &free)| {
+                    free <= lifetime.start && Self::same_texture(&texture.desc, &descriptor)
+                });
+            let physical = match reusable {
+                Some(physical) => physical,
+                None => {
+                    let texture = device.create_texture(&descriptor);
+                    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                    self.textures.push(PhysicalTexture {
+                        desc: descriptor,
+                        texture,
+                        view,
+                    });
+                    free_from.push(0);
+                    self.textures.len() - 1
+                }
+            };
+            free_from[physical] = lifetime.end;
+            self.allocation[resource] = Some(physical);
+        }
+    }
+    fn descriptor(&self, desc: &TextureDesc) -> wgpu::TextureDescriptor<'static> {
+        let (width, height) = match desc.size {
+            TextureSize::Surface => self.size,
+            TextureSize::Fixed { width, height } => (width, height),
+            TextureSize::Downscaled(times) => (self.size.0 >> times, self.size.1 >> times),
+        };
+        wgpu::TextureDescriptor {
+            label: Some(desc.label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: desc.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: desc.format,
+            usage: desc.usage,
+        }
+    }
+    /// Whether a texture made from `a` can stand in for one made from `b`, labels aside.
+    fn same_texture(a: &wgpu::TextureDescriptor, b: &wgpu::TextureDescriptor) -> bool {
+        a.size == b.size
+            && a.sample_count == b.sample_count
+            && a.format == b.format
+            && a.usage == b.usage
+    }
+}
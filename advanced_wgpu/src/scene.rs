@@ -0,0 +1,193 @@
+use std::ops::Range;
+
+use cgmath::prelude::*;
+
+use crate::Instance;
+
+/// A node's transform relative to its parent: scaled first, then rotated, then translated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: cgmath::Vector3<f32>,
+    pub rotation: cgmath::Quaternion<f32>,
+    pub scale: cgmath::Vector3<f32>,
+}
+impl Transform {
+    pub fn from_translation(translation: cgmath::Vector3<f32>) -> Self {
+        Self {
+            translation,
+            ..Default::default()
+        }
+    }
+    pub fn matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.translation)
+            * cgmath::Matrix4::from(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: cgmath::Vector3::zero(),
+            rotation: cgmath::Quaternion::one(),
+            scale: cgmath::Vector3::new(1., 1., 1.),
+        }
+    }
+}
+
+/// Handle to a node in a [`SceneGraph`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+struct Node {
+    local: Transform,
+    /// `local` combined with every ancestor's, as of the last update.
+    world: cgmath::Matrix4<f32>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    /// Index of the mesh drawn at this node, if any.
+    mesh: Option<usize>,
+    /// Axis and speed (per second) the node turns at in [`SceneGraph::animate`].
+    spin: Option<(cgmath::Vector3<f32>, cgmath::Rad<f32>)>,
+    /// Whether `local` changed since `world` was computed.
+    dirty: bool,
+}
+
+/// A hierarchy of nodes with local transforms, for articulated objects: moving a node moves
+/// everything below it. World transforms are only recomputed for the subtrees under nodes
+/// that changed.
+#[derive(Default)]
+pub struct SceneGraph {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+    /// Whether any node is dirty, so unchanged graphs skip the traversal.
+    dirty: bool,
+}
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a node below `parent` (or as a root), drawing `mesh` if given.
+    ///
+    /// # Panics
+    ///
+    /// If `parent` isn't a node of this graph.
+    pub fn add_node(
+        &mut self,
+        parent: Option<NodeId>,
+        local: Transform,
+        mesh: Option<usize>,
+    ) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        self.nodes.push(Node {
+            local,
+            world: cgmath::Matrix4::identity(),
+            parent,
+            children: Vec::new(),
+            mesh,
+            spin: None,
+            dirty: true,
+        });
+        self.dirty = true;
+        id
+    }
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].parent
+    }
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id.0].children
+    }
+    pub fn local_transform(&self, id: NodeId) -> Transform {
+        self.nodes[id.0].local
+    }
+    pub fn set_local_transform(&mut self, id: NodeId, local: Transform) {
+        let node = &mut self.nodes[id.0];
+        node.local = local;
+        node.dirty = true;
+        self.dirty = true;
+    }
+    /// Makes [`Self::animate`] turn the node around `axis` (in its parent's space) at
+    /// `speed` per second, or stop it with `None`.
+    pub fn set_spin(&mut self, id: NodeId, spin: Option<(cgmath::Vector3<f32>, cgmath::Rad<f32>)>) {
+        self.nodes[id.0].spin = spin.map(|(axis, speed)| (axis.normalize(), speed));
+    }
+    /// Turns the spinning nodes by `dt` seconds' worth.
+    pub fn animate(&mut self, dt: f32) {
+        for node in &mut self.nodes {
+            if let Some((axis, speed)) = node.spin {
+                let turn = cgmath::Quaternion::from_axis_angle(axis, speed * dt);
+                node.local.rotation = turn * node.local.rotation;
+                node.dirty = true;
+                self.dirty = true;
+            }
+        }
+    }
+    /// Recomputes the world transforms of the dirty nodes and their descendants, returning
+    /// how many were recomputed.
+    pub fn update_world_transforms(&mut self) -> usize {
+        if !self.dirty {
+            return 0;
+        }
+        self.dirty = false;
+
+        let mut updated = 0;
+        // (node, whether its parent's world transform changed)
+        let mut stack = self
+            .roots
+            .iter()
+            .map(|&root| (root, false))
+            .collect::<Vec<_>>();
+        while let Some((id, parent_changed)) = stack.pop() {
+            let changed = parent_changed || self.nodes[id.0].dirty;
+            if changed {
+                let parent_world = match self.nodes[id.0].parent {
+                    Some(parent) => self.nodes[parent.0].world,
+                    None => cgmath::Matrix4::identity(),
+                };
+                let node = &mut self.nodes[id.0];
+                node.world = parent_world * node.local.matrix();
+                node.dirty = false;
+                updated += 1;
+            }
+            stack.extend(
+                self.nodes[id.0]
+                    .children
+                    .iter()
+                    .map(|&child| (child, changed)),
+            );
+        }
+        updated
+    }
+    /// The node's transform in world space, as of the last [`Self::update_world_transforms`].
+    pub fn world_matrix(&self, id: NodeId) -> cgmath::Matrix4<f32> {
+        self.nodes[id.0].world
+    }
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+    /// The instances of every node with a mesh below `mesh_count`, grouped by mesh, and the
+    /// range of instances per mesh.
+    pub(crate) fn instances(&self, mesh_count: usize) -> (Vec<Instance>, Vec<Range<u32>>) {
+        let mut by_mesh = vec![Vec::new(); mesh_count];
+        for node in &self.nodes {
+            if let Some(transforms) = node.mesh.and_then(|mesh| by_mesh.get_mut(mesh)) {
+                transforms.push(node.world);
+            }
+        }
+
+        let mut instances = Vec::new();
+        let mut mesh_instances = Vec::with_capacity(mesh_count);
+        for transforms in by_mesh {
+            let start = instances.len() as u32;
+            instances.extend(transforms.into_iter().map(Instance::from_matrix));
+            mesh_instances.push(start..instances.len() as u32);
+        }
+        (instances, mesh_instances)
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::*;
+use serde::Deserialize;
+
+use crate::model::{Material, Mesh, Model};
+use crate::{camera, Camera, Instance, Vertex};
+
+/// A scene described in a RON or JSON file: the textures and meshes to load, where to
+/// place instances of them, the camera and the clear color. Paths are relative to the
+/// scene file.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDesc {
+    /// Linear RGB.
+    #[serde(default = "default_clear_color")]
+    pub clear_color: [f64; 3],
+    #[serde(default)]
+    pub camera: CameraDesc,
+    #[serde(default)]
+    pub textures: Vec<TextureDesc>,
+    pub meshes: Vec<MeshDesc>,
+    #[serde(default)]
+    pub instances: Vec<InstanceDesc>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TextureDesc {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MeshDesc {
+    pub name: String,
+    pub source: MeshSource,
+    /// Name of the diffuse texture for built-in meshes; OBJ files bring their own materials.
+    #[serde(default)]
+    pub texture: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum MeshSource {
+    /// The pentagon drawn by default.
+    Pentagon,
+    /// The challenge cube.
+    Cube,
+    /// Every mesh of an OBJ file, with its MTL materials.
+    Obj(PathBuf),
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InstanceDesc {
+    /// Name of the mesh to draw.
+    pub mesh: String,
+    pub position: [f32; 3],
+    /// Rotation about x, y and z in degrees.
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    #[serde(default = "default_scale")]
+    pub scale: [f32; 3],
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraDesc {
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    pub up: [f32; 3],
+    /// Vertical field of view in degrees, for perspective projection.
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    /// Switches to an orthographic projection showing this far above and below the target.
+    pub orthographic_half_height: Option<f32>,
+}
+impl Default for CameraDesc {
+    fn default() -> Self {
+        Self {
+            eye: [0., 1., 2.],
+            target: [0., 0., 0.],
+            up: [0., 1., 0.],
+            fovy: 45.,
+            znear: 0.1,
+            zfar: 100.,
+            orthographic_half_height: None,
+        }
+    }
+}
+
+fn default_clear_color() -> [f64; 3] {
+    [0.1, 0.2, 0.3]
+}
+fn default_scale() -> [f32; 3] {
+    [1., 1., 1.]
+}
+
+impl SceneDesc {
+    /// Reads and validates a scene, as RON or JSON depending on the file extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scene {}", path.display()))?;
+        let desc = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("ron") => Self::from_ron(&source),
+            Some("json") => Self::from_json(&source),
+            _ => bail!(
+                "Unknown scene format {}, expected .ron or .json",
+                path.display()
+            ),
+        };
+        desc.with_context(|| format!("Invalid scene {}", path.display()))
+    }
+    /// Parses and validates a scene in RON. Optional fields can be given without `Some`.
+    pub fn from_ron(source: &str) -> Result<Self> {
+        let desc: Self = ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_str(source)
+            .map_err(|err| anyhow!("{}", err))?;
+        desc.validate()?;
+        Ok(desc)
+    }
+    /// Parses and validates a scene in JSON.
+    pub fn from_json(source: &str) -> Result<Self> {
+        let desc: Self = serde_json::from_str(source).map_err(|err| anyhow!("{}", err))?;
+        desc.validate()?;
+        Ok(desc)
+    }
+    /// Checks what parsing can't: that names are unique and every reference resolves, and
+    /// that the transforms and camera make sense. Files are only checked when loaded.
+    pub fn validate(&self) -> Result<()> {
+        let mut textures = HashSet::new();
+        for texture in &self.textures {
+            if !textures.insert(texture.name.as_str()) {
+                bail!("texture {:?}: defined more than once", texture.name);
+            }
+        }
+
+        let mut meshes = HashSet::new();
+        for mesh in &self.meshes {
+            if !meshes.insert(mesh.name.as_str()) {
+                bail!("mesh {:?}: defined more than once", mesh.name);
+            }
+            match (&mesh.source, &mesh.texture) {
+                (MeshSource::Obj(_), Some(_)) => bail!(
+                    "mesh {:?}: OBJ meshes use their own materials, remove the texture",
+                    mesh.name
+                ),
+                (_, Some(texture)) if !textures.contains(texture.as_str()) => {
+                    bail!("mesh {:?}: unknown texture {:?}", mesh.name, texture)
+                }
+                _ => {}
+            }
+        }
+
+        for (i, instance) in self.instances.iter().enumerate() {
+            if !meshes.contains(instance.mesh.as_str()) {
+                bail!("instance {}: unknown mesh {:?}", i, instance.mesh);
+            }
+            let values = instance
+                .position
+                .iter()
+                .chain(&instance.rotation)
+                .chain(&instance.scale);
+            if !values.clone().all(|value| value.is_finite()) {
+                bail!("instance {} ({}): transform isn't finite", i, instance.mesh);
+            }
+            if instance.scale.contains(&0.) {
+                bail!("instance {} ({}): scale can't be zero", i, instance.mesh);
+            }
+        }
+
+        let camera = &self.camera;
+        if camera.eye == camera.target {
+            bail!("camera: eye and target are the same point");
+        }
+        if !(camera.znear > 0. && camera.zfar > camera.znear) {
+            bail!(
+                "camera: needs 0 < znear < zfar, got znear {} and zfar {}",
+                camera.znear,
+                camera.zfar
+            );
+        }
+        if !(camera.fovy > 0. && camera.fovy < 180.) {
+            bail!(
+                "camera: fovy must be between 0 and 180 degrees, got {}",
+                camera.fovy
+            );
+        }
+        if let Some(half_height) = camera.orthographic_half_height {
+            if half_height.is_nan() || half_height <= 0. {
+                bail!(
+                    "camera: orthographic_half_height must be positive, got {}",
+                    half_height
+                );
+            }
+        }
+        Ok(())
+    }
+    pub(crate) fn camera(&self, aspect: f32) -> Camera {
+        let camera = &self.camera;
+        Camera {
+            eye: camera.eye.into(),
+            target: camera.target.into(),
+            up: camera.up.into(),
+            aspect,
+            projection: match camera.orthographic_half_height {
+                Some(half_height) => camera::Projection::Orthographic { half_height },
+                None => camera::Projection::Perspective,
+            },
+            fovy: camera.fovy,
+            znear: camera.znear,
+            zfar: camera.zfar,
+        }
+    }
+    pub(crate) fn clear_color(&self) -> wgpu::Color {
+        let [r, g, b] = self.clear_color;
+        wgpu::Color { r, g, b, a: 1.0 }
+    }
+    /// Loads the textures and meshes (relative to `base_dir`) into one model, with the
+    /// instances grouped by mesh.
+    pub(crate) fn build(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        base_dir: &Path,
+    ) -> Result<(Model, Vec<Instance>, Vec<Range<u32>>)> {
+        let mut materials = Vec::new();
+        for desc in &self.textures {
+            let file_name = desc.path.to_string_lossy();
+            let diffuse_texture = Model::load_texture(device, queue, base_dir, &file_name, true)
+                .with_context(|| format!("texture {:?}: can't load {}", desc.name, file_name))?;
+            let normal_texture = Material::flat_normal_texture(device, queue)?;
+            materials.push(Material::new(
+                device,
+                &desc.name,
+                diffuse_texture,
+                normal_texture,
+                layout,
+            ));
+        }
+        // Shared by the built-in meshes without a texture, created on first use
+        let mut untextured_material = None;
+
+        let mut meshes = Vec::new();
+        let mut instances = Vec::new();
+        let mut mesh_instances = Vec::new();
+        for desc in &self.meshes {
+            let start = instances.len() as u32;
+            instances.extend(
+                self.instances
+                    .iter()
+                    .filter(|instance| instance.mesh == desc.name)
+                    .map(InstanceDesc::instance),
+            );
+            let range = start..instances.len() as u32;
+
+            let (vertices, indices) = match &desc.source {
+                MeshSource::Pentagon => (crate::VERTICES, crate::INDICES),
+                MeshSource::Cube => (crate::VERTICES_CHAL, crate::INDICES_CHAL),
+                MeshSource::Obj(path) => {
+                    let path = base_dir.join(path);
+                    let model = Model::load(device, queue, &path, layout).with_context(|| {
+                        format!("mesh {:?}: can't load {}", desc.name, path.display())
+                    })?;
+                    let material_offset = materials.len();
+                    materials.extend(model.materials);
+                    for mut mesh in model.meshes {
+                        mesh.material += material_offset;
+                        meshes.push(mesh);
+                        mesh_instances.push(range.clone());
+                    }
+                    continue;
+                }
+            };
+            let material = match &desc.texture {
+                Some(texture) => self
+                    .textures
+                    .iter()
+                    .position(|desc| &desc.name == texture)
+                    .expect("validated"),
+                None => match untextured_material {
+                    Some(material) => material,
+                    None => {
+                        materials.push(Material::new(
+                            device,
+                            "untextured",
+                            Material::solid_color_texture(device, queue, [255, 255, 255, 255])?,
+                            Material::flat_normal_texture(device, queue)?,
+                            layout,
+                        ));
+                        untextured_material = Some(materials.len() - 1);
+                        materials.len() - 1
+                    }
+                },
+            };
+            let indices = indices
+                .iter()
+                .map(|&index| index as u32)
+                .collect::<Vec<_>>();
+            meshes.push(Mesh::new(
+                device,
+                &desc.name,
+                &Vertex::with_tangents(vertices, &indices),
+                &indices,
+                material,
+            ));
+            mesh_instances.push(range);
+        }
+
+        Ok((Model { meshes, materials }, instances, mesh_instances))
+    }
+}
+
+impl InstanceDesc {
+    fn instance(&self) -> Instance {
+        let [x, y, z] = self.rotation;
+        Instance {
+            position: self.position.into(),
+            rotation: cgmath::Quaternion::from(cgmath::Euler::new(
+                cgmath::Deg(x),
+                cgmath::Deg(y),
+                cgmath::Deg(z),
+            )),
+            scale: self.scale.into(),
+        }
+    }
+}
@@ -1,13 +1,36 @@
 struct CameraUniform {
+    view_position: vec4<f32>,
     view_proj: mat4x4<f32>,
 }
 @group(1) @binding(0)
 var<uniform> camera: CameraUniform;
 
+struct Light {
+    position: vec3<f32>,
+    intensity: f32,
+    color: vec3<f32>,
+}
+@group(2) @binding(0)
+var<uniform> light: Light;
+
+struct ShadowUniform {
+    light_view_proj: mat4x4<f32>,
+    texel_size: f32,
+}
+@group(3) @binding(0)
+var<uniform> shadow: ShadowUniform;
+@group(3) @binding(1)
+var t_shadow: texture_depth_2d;
+@group(3) @binding(2)
+var s_shadow: sampler_comparison;
+
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
 }
 
 struct InstanceInput {
@@ -15,12 +38,20 @@ struct InstanceInput {
     @location(6) model_matrix_1: vec4<f32>,
     @location(7) model_matrix_2: vec4<f32>,
     @location(8) model_matrix_3: vec4<f32>,
+    @location(9) normal_matrix_0: vec3<f32>,
+    @location(10) normal_matrix_1: vec3<f32>,
+    @location(11) normal_matrix_2: vec3<f32>,
 }
 
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+    @location(3) light_space_position: vec4<f32>,
+    @location(4) world_tangent: vec3<f32>,
+    @location(5) world_bitangent: vec3<f32>,
 };
 
 
@@ -32,9 +63,20 @@ fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
         instance.model_matrix_2,
         instance.model_matrix_3,
     );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.world_normal = normal_matrix * model.normal;
+    out.world_tangent = normal_matrix * model.tangent;
+    out.world_bitangent = normal_matrix * model.bitangent;
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+    out.world_position = world_position.xyz;
+    out.light_space_position = shadow.light_view_proj * world_position;
+    out.clip_position = camera.view_proj * world_position;
     return out;
 }
 
@@ -43,8 +85,58 @@ fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
 var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
+@group(0) @binding(2)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(3)
+var s_normal: sampler;
+
+// Percentage-closer filtering: the fraction of a 3x3 texel neighbourhood that is lit
+fn shadow_factor(light_space_position: vec4<f32>) -> f32 {
+    let projected = light_space_position.xyz / light_space_position.w;
+    let uv = projected.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5, 0.5);
+    // Everything outside the light's frustum is lit, instead of taking the edge texels' depth
+    if (projected.z > 1.0 || any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0))) {
+        return 1.0;
+    }
+
+    var lit = 0.0;
+    for (var x = -1; x <= 1; x += 1) {
+        for (var y = -1; y <= 1; y += 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * shadow.texel_size;
+            lit += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, projected.z);
+        }
+    }
+    return lit / 9.0;
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
-}
\ No newline at end of file
+    let object_color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let light_color = light.color * light.intensity;
+
+    let ambient_strength = 0.1;
+    let ambient_color = light_color * ambient_strength;
+
+    // Perturb the interpolated normal by the normal map, in tangent space
+    let tangent_normal = textureSample(t_normal, s_normal, in.tex_coords).xyz * 2.0 - 1.0;
+    let tbn = mat3x3<f32>(
+        normalize(in.world_tangent),
+        normalize(in.world_bitangent),
+        normalize(in.world_normal),
+    );
+    let normal = normalize(tbn * tangent_normal);
+    let light_dir = normalize(light.position - in.world_position);
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+    let half_dir = normalize(view_dir + light_dir);
+
+    let diffuse_strength = max(dot(normal, light_dir), 0.0);
+    let diffuse_color = light_color * diffuse_strength;
+
+    let specular_strength = pow(max(dot(normal, half_dir), 0.0), 32.0);
+    let specular_color = light_color * specular_strength;
+
+    let visibility = shadow_factor(in.light_space_position);
+
+    let result = (ambient_color + visibility * (diffuse_color + specular_color)) * object_color.xyz;
+    return vec4<f32>(result, object_color.a);
+}
@@ -0,0 +1,285 @@
+use wgpu::util::DeviceExt;
+
+use cgmath::prelude::*;
+
+use crate::texture;
+use crate::{InstanceRaw, Vertex, OPENGL_TO_WGPU_MATRIX};
+
+/// Shadow map parameters for the directional light.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowSettings {
+    /// Width and height of the shadow map in texels.
+    pub resolution: u32,
+    /// Constant depth bias added while rendering the shadow map, to avoid shadow acne.
+    pub depth_bias: i32,
+    /// Depth bias scaled by the slope of each triangle.
+    pub slope_bias: f32,
+    /// Half the width and height of the light's orthographic frustum.
+    pub frustum_half_extent: f32,
+    pub frustum_near: f32,
+    /// The frustum is centred on the origin, so this is also twice the light's distance to it.
+    pub frustum_far: f32,
+}
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            depth_bias: 2,
+            slope_bias: 2.0,
+            frustum_half_extent: 8.0,
+            frustum_near: 0.1,
+            frustum_far: 30.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    light_view_proj: [[f32; 4]; 4],
+    texel_size: f32,
+    _padding: [u32; 3],
+}
+
+/// Depth map rendered from the light, sampled by the main pass with PCF.
+pub(crate) struct ShadowMap {
+    settings: ShadowSettings,
+    texture: texture::Texture,
+    uniform: ShadowUniform,
+    buffer: wgpu::Buffer,
+    pass_bind_group: wgpu::BindGroup,
+    pass_pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    /// Layout of `bind_group`, for the pipelines that receive shadows.
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+impl ShadowMap {
+    pub fn new(device: &wgpu::Device, settings: ShadowSettings) -> Self {
+        let texture = texture::Texture::create_depth_texture_with_size(
+            device,
+            settings.resolution,
+            settings.resolution,
+            1,
+            "shadow_map",
+        );
+        let uniform = ShadowUniform {
+            light_view_proj: cgmath::Matrix4::identity().into(),
+            texel_size: 1. / settings.resolution as f32,
+            _padding: [0; 3],
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let pass_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("shadow_pass_bind_group_layout"),
+            });
+        let pass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pass_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("shadow_pass_bind_group"),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+            label: Some("shadow_bind_group_layout"),
+        });
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, &buffer, &texture);
+
+        let pass_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&pass_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Self::create_pipeline(device, &pass_pipeline_layout, &settings);
+
+        Self {
+            settings,
+            texture,
+            uniform,
+            buffer,
+            pass_bind_group,
+            pass_pipeline_layout,
+            pipeline,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+    pub fn settings(&self) -> ShadowSettings {
+        self.settings
+    }
+    /// Recreates the shadow map and pipeline for new settings.
+    pub fn set_settings(&mut self, device: &wgpu::Device, settings: ShadowSettings) {
+        self.settings = settings;
+        self.texture = texture::Texture::create_depth_texture_with_size(
+            device,
+            settings.resolution,
+            settings.resolution,
+            1,
+            "shadow_map",
+        );
+        self.uniform.texel_size = 1. / settings.resolution as f32;
+        self.bind_group =
+            Self::create_bind_group(device, &self.bind_group_layout, &self.buffer, &self.texture);
+        self.pipeline = Self::create_pipeline(device, &self.pass_pipeline_layout, &settings);
+    }
+    /// Points the light's frustum from `light_position` at the origin.
+    pub fn update(&mut self, queue: &wgpu::Queue, light_position: cgmath::Vector3<f32>) {
+        let target = cgmath::Point3::origin();
+        let direction = (target - cgmath::Point3::from_vec(light_position)).normalize();
+        let eye = target - direction * (self.settings.frustum_far * 0.5);
+        let up = if direction.cross(cgmath::Vector3::unit_y()).is_zero() {
+            cgmath::Vector3::unit_z()
+        } else {
+            cgmath::Vector3::unit_y()
+        };
+
+        let extent = self.settings.frustum_half_extent;
+        let view = cgmath::Matrix4::look_at_rh(eye, target, up);
+        let proj = cgmath::ortho(
+            -extent,
+            extent,
+            -extent,
+            extent,
+            self.settings.frustum_near,
+            self.settings.frustum_far,
+        );
+        self.uniform.light_view_proj = (OPENGL_TO_WGPU_MATRIX * proj * view).into();
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+    /// Starts the depth-only pass from the light; the caller draws the shadow casters
+    /// with the vertex buffer in slot 0 and the instance buffer in slot 1.
+    pub fn begin_pass<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder) -> wgpu::RenderPass<'a> {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow Pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.pass_bind_group, &[]);
+        render_pass
+    }
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+        texture: &texture::Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some("shadow_bind_group"),
+        })
+    }
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        settings: &ShadowSettings,
+    ) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shadow.wgsl").into()),
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Render Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc(), InstanceRaw::desc()],
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // The pentagons are single sided, so both faces have to cast shadows
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: settings.depth_bias,
+                    slope_scale: settings.slope_bias,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+}
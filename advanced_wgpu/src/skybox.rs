@@ -0,0 +1,139 @@
+use crate::{pipeline, texture};
+
+/// Where a skybox's cubemap was loaded from, so it can be loaded again on a new device.
+#[derive(Clone, Debug)]
+pub(crate) enum SkyboxSource {
+    /// The equirectangular sky built into the binary.
+    BuiltIn,
+    Equirectangular(std::path::PathBuf),
+    /// +X, -X, +Y, -Y, +Z and -Z.
+    Faces([std::path::PathBuf; 6]),
+}
+
+/// Texels per side of the cubemap faces converted from an equirectangular image.
+pub const EQUIRECT_FACE_SIZE: u32 = 256;
+
+/// The format equirectangular skies are converted into: a float format that keeps the
+/// brightness of `.hdr` skies if the adapter can render to it, otherwise an 8-bit one.
+pub fn equirect_format(hdr_supported: bool) -> wgpu::TextureFormat {
+    if hdr_supported {
+        crate::hdr::HDR_FORMAT
+    } else {
+        wgpu::TextureFormat::Rgba8UnormSrgb
+    }
+}
+
+/// A cubemap drawn on the far plane wherever the scene left the depth buffer clear, so it
+/// shows behind everything. Only the camera's rotation moves it.
+///
+/// Create it once per device: its pipeline layout is registered under `"skybox"`, and
+/// [`Skybox::set_cubemap`] swaps the sky.
+pub(crate) struct Skybox {
+    #[allow(dead_code)]
+    cubemap: texture::Texture,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pub pipeline: pipeline::PipelineId,
+}
+impl Skybox {
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut pipeline::PipelineCache,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        cubemap: texture::Texture,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("skybox_bind_group_layout"),
+        });
+        pipelines.add_layout(
+            "skybox",
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Skybox Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            }),
+        );
+        let pipeline = pipelines.get_or_create(
+            device,
+            &pipeline::PipelineDesc::new(
+                "Skybox Pipeline",
+                include_str!("skybox.wgsl"),
+                "skybox",
+                format,
+            )
+            .cull_mode(None)
+            // Drawn at depth 1, which only passes where the depth buffer is still clear
+            .depth(pipeline::DepthState {
+                compare: wgpu::CompareFunction::LessEqual,
+                write_enabled: false,
+                ..Default::default()
+            })
+            .sample_count(sample_count),
+        );
+
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, &cubemap);
+        Self {
+            cubemap,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        }
+    }
+    pub fn set_cubemap(&mut self, device: &wgpu::Device, cubemap: texture::Texture) {
+        self.bind_group = Self::create_bind_group(device, &self.bind_group_layout, &cubemap);
+        self.cubemap = cubemap;
+    }
+    /// Draws the sky with the camera bound at group 0, into a pass whose depth attachment
+    /// holds the scene's depth.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        pipelines: &'a pipeline::PipelineCache,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&pipelines[self.pipeline]);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        cubemap: &texture::Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&cubemap.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&cubemap.sampler),
+                },
+            ],
+            label: Some("skybox_bind_group"),
+        })
+    }
+}
@@ -0,0 +1,41 @@
+struct CameraUniform {
+    view_position: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    // Inverse of the projection times the view's rotation only, so the sky stays put as
+    // the camera moves
+    sky_inv_view_proj: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var t_sky: texture_cube<f32>;
+@group(1) @binding(1)
+var s_sky: sampler;
+
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) clip: vec2<f32>,
+};
+
+
+// A triangle covering the whole target on the far plane, behind everything already drawn
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip = corner * 2.0 - 1.0;
+    out.clip_position = vec4<f32>(out.clip, 1.0, 1.0);
+    return out;
+}
+
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // From the near to the far plane, which also works for orthographic projection
+    let near = camera.sky_inv_view_proj * vec4<f32>(in.clip, 0.0, 1.0);
+    let far = camera.sky_inv_view_proj * vec4<f32>(in.clip, 1.0, 1.0);
+    let direction = far.xyz / far.w - near.xyz / near.w;
+    return vec4<f32>(textureSample(t_sky, s_sky, direction).rgb, 1.0);
+}
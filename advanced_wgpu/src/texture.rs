@@ -1,5 +1,37 @@
 use anyhow::*;
 use image::GenericImageView;
+use wgpu::util::DeviceExt;
+
+/// How a texture's sampler filters between texels and mip levels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filtering {
+    /// Linear filtering within the nearest mip level, without blending between levels.
+    Bilinear,
+    /// Linear filtering within and between mip levels.
+    Trilinear,
+    /// Trilinear filtering with the given anisotropy clamp (a power of two, at most 16).
+    /// Falls back to trilinear on adapters without anisotropic filtering.
+    Anisotropic(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureOptions {
+    /// Generate the full mip chain on the CPU instead of uploading a single level.
+    pub generate_mipmaps: bool,
+    pub filtering: Filtering,
+    /// Store the texels as sRGB colors. Turn this off for data such as normal maps,
+    /// which must be sampled without the sRGB to linear conversion.
+    pub srgb: bool,
+}
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            generate_mipmaps: false,
+            filtering: Filtering::Bilinear,
+            srgb: true,
+        }
+    }
+}
 
 pub struct Texture {
     pub texture: wgpu::Texture,
@@ -7,22 +39,40 @@ pub struct Texture {
     pub sampler: wgpu::Sampler,
 }
 impl Texture {
-    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = crate::pipeline::DEPTH_FORMAT;
 
     pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         bytes: &[u8],
         label: &str,
+    ) -> Result<Self> {
+        Self::from_bytes_with_options(device, queue, bytes, label, TextureOptions::default())
+    }
+    pub fn from_bytes_with_options(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        options: TextureOptions,
     ) -> Result<Self> {
         let image = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &image, Some(label))
+        Self::from_image_with_options(device, queue, &image, Some(label), options)
     }
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         image: &image::DynamicImage,
         label: Option<&str>,
+    ) -> Result<Self> {
+        Self::from_image_with_options(device, queue, image, label, TextureOptions::default())
+    }
+    pub fn from_image_with_options(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::DynamicImage,
+        label: Option<&str>,
+        options: TextureOptions,
     ) -> Result<Self> {
         let rgba = image.to_rgba8();
         let dimensions = image.dimensions();
@@ -31,16 +81,126 @@ impl Texture {
             height: dimensions.1,
             depth_or_array_layers: 1,
         };
+        let mip_level_count = if options.generate_mipmaps {
+            Self::mip_level_count(dimensions.0, dimensions.1)
+        } else {
+            1
+        };
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format: if options.srgb {
+                wgpu::TextureFormat::Rgba8UnormSrgb
+            } else {
+                wgpu::TextureFormat::Rgba8Unorm
+            },
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         });
 
+        for mip_level in 0..mip_level_count {
+            // Each level is downsampled from the full image rather than the previous level
+            // so filtering errors don't accumulate down the chain.
+            let resized;
+            let level = if mip_level == 0 {
+                &rgba
+            } else {
+                resized = image::imageops::resize(
+                    &rgba,
+                    (dimensions.0 >> mip_level).max(1),
+                    (dimensions.1 >> mip_level).max(1),
+                    image::imageops::FilterType::Triangle,
+                );
+                &resized
+            };
+            let level_size = wgpu::Extent3d {
+                width: level.width(),
+                height: level.height(),
+                depth_or_array_layers: 1,
+            };
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                level,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(4 * level.width()),
+                    rows_per_image: std::num::NonZeroU32::new(level.height()),
+                },
+                level_size,
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&Self::sampler_descriptor(options.filtering));
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+    /// Loads a color-grading lookup table from an image of `n` slices of `n`x`n` texels side
+    /// by side (e.g. 256x16 for `n` = 16). Red goes along x within a slice, green along y and
+    /// blue from slice to slice, which becomes a 3D texture indexed by (r, g, b).
+    pub fn lut_from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+    ) -> Result<Self> {
+        let image = image::load_from_memory(bytes)?;
+        Self::lut_from_image(device, queue, &image, label)
+    }
+    pub fn lut_from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::DynamicImage,
+        label: &str,
+    ) -> Result<Self> {
+        let (width, height) = image.dimensions();
+        ensure!(
+            height > 1 && width == height * height,
+            "{}: a {}x{} image isn't a LUT, expected {}x{}",
+            label,
+            width,
+            height,
+            height * height,
+            height
+        );
+        let n = height;
+        let rgba = image.to_rgba8();
+        // Slice b starts at x = b * n and becomes depth layer b
+        let mut texels = Vec::with_capacity((n * n * n * 4) as usize);
+        for b in 0..n {
+            for g in 0..n {
+                for r in 0..n {
+                    texels.extend_from_slice(&rgba.get_pixel(b * n + r, g).0);
+                }
+            }
+        }
+
+        let size = wgpu::Extent3d {
+            width: n,
+            height: n,
+            depth_or_array_layers: n,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            // Maps encoded colors to encoded colors, so no sRGB conversion
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
         queue.write_texture(
             wgpu::ImageCopyTexture {
                 texture: &texture,
@@ -48,11 +208,11 @@ impl Texture {
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            &rgba,
+            &texels,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
-                rows_per_image: std::num::NonZeroU32::new(dimensions.1),
+                bytes_per_row: std::num::NonZeroU32::new(4 * n),
+                rows_per_image: std::num::NonZeroU32::new(n),
             },
             size,
         );
@@ -63,49 +223,450 @@ impl Texture {
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
-
         Ok(Self {
             texture,
             view,
             sampler,
         })
     }
-    pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Self {
+    /// A `n`x`n`x`n` lookup table that leaves colors unchanged.
+    pub fn identity_lut(device: &wgpu::Device, queue: &wgpu::Queue, n: u32) -> Self {
+        let scale = |value: u32| (value * 255 / (n - 1)) as u8;
+        let image = image::RgbaImage::from_fn(n * n, n, |x, y| {
+            image::Rgba([scale(x % n), scale(y), scale(x / n), 255])
+        });
+        Self::lut_from_image(device, queue, &image.into(), "identity_lut")
+            .expect("identity LUT has the LUT layout")
+    }
+    /// Loads a cubemap from six square images of the same size, in the order +X, -X, +Y,
+    /// -Y, +Z, -Z. The view has `TextureViewDimension::Cube`.
+    pub fn cubemap_from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: [&[u8]; 6],
+        label: &str,
+    ) -> Result<Self> {
+        let mut images = Vec::with_capacity(6);
+        for (i, bytes) in faces.into_iter().enumerate() {
+            images.push(
+                image::load_from_memory(bytes)
+                    .with_context(|| format!("{}: couldn't decode face {}", label, i))?,
+            );
+        }
+        Self::cubemap_from_images(device, queue, &images, label)
+    }
+    pub fn cubemap_from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::DynamicImage],
+        label: &str,
+    ) -> Result<Self> {
+        ensure!(
+            faces.len() == 6,
+            "{}: a cubemap has 6 faces, got {}",
+            label,
+            faces.len()
+        );
+        let (size, _) = faces[0].dimensions();
+        for (i, face) in faces.iter().enumerate() {
+            ensure!(
+                face.dimensions() == (size, size),
+                "{}: face {} is {}x{}, expected {}x{} like the first",
+                label,
+                i,
+                face.width(),
+                face.height(),
+                size,
+                size
+            );
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        for (layer, face) in faces.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &face.to_rgba8(),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(4 * size),
+                    rows_per_image: std::num::NonZeroU32::new(size),
+                },
+                wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        Ok(Self::from_cube_texture(device, texture))
+    }
+    /// Converts an equirectangular (latitude/longitude) image into a cubemap in `format`
+    /// with `face_size` texels per side, on the GPU. PNG and JPEG images are taken as sRGB.
+    ///
+    /// The adapter has to be able to render to `format`. Radiance `.hdr` files only keep
+    /// their full range in a float format like `Rgba16Float` (see
+    /// `hdr::HdrTarget::is_supported`), `Rgba8UnormSrgb` clamps them to 1.
+    pub fn cubemap_from_equirectangular(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        face_size: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Result<Self> {
+        let (width, height, texels): (u32, u32, Vec<f32>) = if bytes.starts_with(b"#?") {
+            let decoder = image::codecs::hdr::HdrDecoder::new(bytes)
+                .with_context(|| format!("{}: invalid Radiance file", label))?;
+            let metadata = decoder.metadata();
+            // Checked before decoding, which allocates the whole image up front
+            let max = device.limits().max_texture_dimension_2d;
+            ensure!(
+                (1..=max).contains(&metadata.width) && (1..=max).contains(&metadata.height),
+                "{}: a {}x{} image doesn't fit in a texture, at most {}x{}",
+                label,
+                metadata.width,
+                metadata.height,
+                max,
+                max
+            );
+            let texels = decoder
+                .read_image_hdr()
+                .with_context(|| format!("{}: invalid Radiance file", label))?
+                .into_iter()
+                .flat_map(|image::Rgb([r, g, b])| [r, g, b, 1.0])
+                .collect();
+            (metadata.width, metadata.height, texels)
+        } else {
+            let image = image::load_from_memory(bytes)?.to_rgba32f();
+            let linear = |c: f32| {
+                if c <= 0.04045 {
+                    c / 12.92
+                } else {
+                    ((c + 0.055) / 1.055).powf(2.4)
+                }
+            };
+            let texels = image
+                .pixels()
+                .flat_map(|p| [linear(p[0]), linear(p[1]), linear(p[2]), p[3]])
+                .collect();
+            (image.width(), image.height(), texels)
+        };
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        // Only read with textureLoad, as 32-bit floats can't be filtered everywhere
+        let equirect = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &equirect,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&texels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(16 * width),
+                rows_per_image: std::num::NonZeroU32::new(height),
+            },
+            size,
+        );
+        let equirect_view = equirect.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let cube = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: face_size,
+                height: face_size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("equirect_bind_group_layout"),
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Equirectangular To Cube Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                crate::post::fullscreen_shader(include_str!("equirect_to_cube.wgsl")).into(),
+            ),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Equirectangular To Cube Pipeline"),
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Equirectangular To Cube Pipeline Layout"),
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                }),
+            ),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Equirectangular To Cube Encoder"),
+        });
+        // The buffers and bind groups have to outlive the encoder's commands
+        let mut faces = Vec::with_capacity(6);
+        for layer in 0..6u32 {
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Cube Face Buffer"),
+                // Padded to the 16 bytes uniform buffers need on WebGL2
+                contents: bytemuck::cast_slice(&[layer, 0, 0, 0]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&equirect_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("equirect_bind_group"),
+            });
+            let view = cube.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            });
+            faces.push((buffer, bind_group, view));
+        }
+        for (_, bind_group, view) in &faces {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Equirectangular To Cube Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(Self::from_cube_texture(device, cube))
+    }
+    fn from_cube_texture(device: &wgpu::Device, texture: wgpu::Texture) -> Self {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+    pub fn mip_level_count(width: u32, height: u32) -> u32 {
+        32 - width.max(height).max(1).leading_zeros()
+    }
+    fn sampler_descriptor<'a>(filtering: Filtering) -> wgpu::SamplerDescriptor<'a> {
+        let (min_filter, mipmap_filter, anisotropy_clamp) = match filtering {
+            Filtering::Bilinear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest, None),
+            Filtering::Trilinear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear, None),
+            Filtering::Anisotropic(clamp) => (
+                wgpu::FilterMode::Linear,
+                wgpu::FilterMode::Linear,
+                std::num::NonZeroU8::new(clamp),
+            ),
+        };
+        wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter,
+            mipmap_filter,
+            anisotropy_clamp,
+            ..Default::default()
+        }
+    }
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        label: &str,
+    ) -> Self {
         let size = wgpu::Extent3d {
             width: config.width,
             height: config.height,
             depth_or_array_layers: 1,
         };
-        let desc = wgpu::TextureDescriptor {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some(label),
             size,
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+    pub fn create_depth_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        Self::create_depth_texture_with_size(
+            device,
+            config.width,
+            config.height,
+            sample_count,
+            label,
+        )
+    }
+    pub fn create_depth_texture_with_size(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let desc = wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            // Multisampled depth can't be sampled with a comparison sampler anyway
+            usage: if sample_count > 1 {
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+            } else {
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            },
         };
         let texture = device.create_texture(&desc);
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(
-            &wgpu::SamplerDescriptor {
-                address_mode_u: wgpu::AddressMode::ClampToEdge,
-                address_mode_v: wgpu::AddressMode::ClampToEdge,
-                address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: wgpu::FilterMode::Linear,
-                min_filter: wgpu::FilterMode::Linear,
-                mipmap_filter: wgpu::FilterMode::Nearest,
-                compare: Some(wgpu::CompareFunction::LessEqual),
-                lod_min_clamp: -100.,
-                lod_max_clamp: 100.,
-                ..Default::default()
-            }
-        );
-        Self { texture, view, sampler }
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            lod_min_clamp: -100.,
+            lod_max_clamp: 100.,
+            ..Default::default()
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+        }
     }
 }
@@ -0,0 +1,53 @@
+struct Tonemap {
+    exposure: f32,
+    gamma: f32,
+    // 0: clamp, 1: Reinhard, 2: ACES
+    tonemapping: u32,
+    // Set when the surface isn't sRGB, so the shader has to encode the output itself
+    encode_srgb: u32,
+}
+
+@group(0) @binding(0)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> tonemap: Tonemap;
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp(
+        (color * (a * color + b)) / (color * (c * color + d) + e),
+        vec3<f32>(0.0),
+        vec3<f32>(1.0),
+    );
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let scene = textureLoad(t_scene, vec2<i32>(in.clip_position.xy), 0);
+    let color = max(scene.rgb * tonemap.exposure, vec3<f32>(0.0));
+
+    var mapped: vec3<f32>;
+    switch tonemap.tonemapping {
+        case 1u: {
+            mapped = color / (1.0 + color);
+        }
+        case 2u: {
+            mapped = aces(color);
+        }
+        default: {
+            mapped = min(color, vec3<f32>(1.0));
+        }
+    }
+
+    // sRGB is close to a gamma of 2.2, so only the difference is applied here
+    mapped = pow(mapped, vec3<f32>(2.2 / tonemap.gamma));
+    if (tonemap.encode_srgb != 0u) {
+        mapped = srgb_encode(mapped);
+    }
+    return vec4<f32>(mapped, scene.a);
+}
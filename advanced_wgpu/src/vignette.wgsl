@@ -0,0 +1,36 @@
+struct Vignette {
+    // How dark the corners get, from 0 to 1
+    intensity: f32,
+    // Distance from the center where darkening starts, 1 being the corners
+    radius: f32,
+    // Distance over which it fades in
+    smoothness: f32,
+    // Set when the surface is sRGB, so the darkening is applied to encoded values
+    srgb_surface: u32,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(0) @binding(2)
+var<uniform> vignette: Vignette;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let input = textureSample(t_input, s_input, in.uv);
+    var color = clamp(input.rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+    if (vignette.srgb_surface != 0u) {
+        color = srgb_encode(color);
+    }
+
+    // 0 in the center, 1 in the corners
+    let from_center = length(in.uv - 0.5) * sqrt(2.0);
+    let falloff = smoothstep(vignette.radius, vignette.radius + vignette.smoothness, from_center);
+    color = color * (1.0 - falloff * vignette.intensity);
+
+    if (vignette.srgb_surface != 0u) {
+        color = srgb_decode(color);
+    }
+    return vec4<f32>(color, input.a);
+}
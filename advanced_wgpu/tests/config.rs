@@ -0,0 +1,92 @@
+//! Command-line flags and TOML config files.
+
+use advanced_wgpu::config::{Backend, Config, PowerPreference, PresentMode, SurfaceFormat};
+
+#[test]
+fn flags_override_the_config_file() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("config.toml");
+    std::fs::write(
+        &path,
+        r#"
+            backend = "vulkan"
+            power_preference = "high-performance"
+            present_mode = "mailbox"
+            width = 1280
+            height = 720
+        "#,
+    )
+    .unwrap();
+
+    let config = Config::from_args([
+        "--present-mode",
+        "immediate",
+        "--config",
+        path.to_str().unwrap(),
+        "--surface-format",
+        "bgra8unorm-srgb",
+        "res/cube.obj",
+    ])
+    .unwrap();
+    assert_eq!(
+        config,
+        Config {
+            backend: Backend::Vulkan,
+            power_preference: PowerPreference::HighPerformance,
+            present_mode: PresentMode::Immediate,
+            surface_format: Some(SurfaceFormat::Bgra8unormSrgb),
+            width: Some(1280),
+            height: Some(720),
+            file: Some("res/cube.obj".into()),
+            ..Default::default()
+        }
+    );
+    assert_eq!(wgpu::Backends::from(config.backend), wgpu::Backends::VULKAN);
+}
+
+#[test]
+fn flags() {
+    let config = Config::from_args([
+        "--size",
+        "640x480",
+        "--fullscreen",
+        "--fallback-adapter",
+        "--backend",
+        "gl",
+        "--msaa",
+        "4",
+    ])
+    .unwrap();
+    assert_eq!(
+        config.window_size(),
+        Some(winit::dpi::PhysicalSize::new(640, 480))
+    );
+    assert!(config.fullscreen);
+    assert!(config.force_fallback_adapter);
+    assert_eq!(config.backend, Backend::Gl);
+    assert_eq!(config.msaa_samples, Some(4));
+    assert_eq!(
+        Config::from_args(Vec::<String>::new()).unwrap(),
+        Config::default()
+    );
+}
+
+#[test]
+fn errors_name_the_option() {
+    let error = |args: &[&str]| format!("{:#}", Config::from_args(args.to_vec()).unwrap_err());
+    assert_eq!(
+        error(&["--backend", "opengl"]),
+        "--backend: unknown variant `opengl`, expected one of `all`, `primary`, `vulkan`, \
+         `metal`, `dx12`, `dx11`, `gl`, `browser-webgpu`"
+    );
+    assert_eq!(
+        error(&["--size", "640"]),
+        r#"--size: expected WIDTHxHEIGHT, got "640""#
+    );
+    assert_eq!(
+        error(&["--msaa", "3"]),
+        "--msaa: expected 1, 2, 4 or 8 samples, got 3"
+    );
+    assert_eq!(error(&["--present-mode"]), "--present-mode: missing value");
+    assert_eq!(error(&["--vsync"]), "Unknown option --vsync");
+    assert!(error(&["--config", "missing.toml"]).starts_with("Failed to read config missing.toml"));
+}
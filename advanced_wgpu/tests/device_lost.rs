@@ -0,0 +1,121 @@
+//! Telling a lost device apart from other wgpu errors and panics.
+
+use std::panic;
+
+use advanced_wgpu::device_lost::{is_device_lost_error, DeviceLost};
+
+fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::default(),
+        compatible_surface: None,
+        force_fallback_adapter: false,
+    }));
+    let Some(adapter) = adapter else {
+        eprintln!("Skipping device-lost test: no adapter");
+        return None;
+    };
+    pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::downlevel_webgl2_defaults(),
+        },
+        None,
+    ))
+    .ok()
+}
+
+/// Buffers mapped at creation have to be a multiple of 4 bytes long.
+fn create_invalid_buffer(device: &wgpu::Device) {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Invalid Buffer"),
+        size: 3,
+        usage: wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: true,
+    });
+}
+
+/// wgpu-core's error for calls on a lost device.
+#[derive(Debug)]
+struct Lost;
+impl std::fmt::Display for Lost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("parent device is lost")
+    }
+}
+impl std::error::Error for Lost {}
+
+#[test]
+fn validation_error_is_not_device_lost() {
+    let Some((device, _queue)) = device() else {
+        return;
+    };
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    create_invalid_buffer(&device);
+    let error = pollster::block_on(device.pop_error_scope()).expect("the buffer is invalid");
+    assert!(!is_device_lost_error(&error), "{}", error);
+}
+
+#[test]
+fn lost_device_error_is_detected() {
+    let error = wgpu::Error::Validation {
+        source: Box::new(Lost),
+        description: "Validation Error\n\nCaused by:\n    In Device::create_buffer\n".to_string(),
+    };
+    assert!(is_device_lost_error(&error));
+}
+
+#[test]
+fn other_uncaptured_errors_still_panic() {
+    let Some((device, _queue)) = device() else {
+        return;
+    };
+    let lost = DeviceLost::watch(&device);
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| create_invalid_buffer(&device)));
+    assert!(result.is_err());
+    assert!(!lost.is_lost());
+}
+
+#[test]
+fn lost_device_panic_sets_the_flag() {
+    let lost = DeviceLost::default();
+    assert_eq!(lost.catch(|| 1), Some(1));
+    assert!(!lost.is_lost());
+
+    // How `Queue::submit` reports it
+    let caught = lost.catch(|| panic!("Error in Queue::submit: {}", Lost));
+    assert_eq!(caught, None::<()>);
+    assert!(lost.is_lost());
+    assert!(lost.clone().is_lost());
+}
+
+#[test]
+fn panics_mentioning_a_lost_device_keep_unwinding() {
+    let lost = DeviceLost::default();
+    // Not wgpu's fatal error for a lost device, only a message that happens to mention it
+    let result = panic::catch_unwind(|| lost.catch(|| panic!("the device is lost, {}", Lost)));
+    assert!(result.is_err());
+    assert!(!lost.is_lost());
+}
+
+#[test]
+fn other_panics_keep_unwinding() {
+    let Some((device, queue)) = device() else {
+        return;
+    };
+    let lost = DeviceLost::watch(&device);
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Small Buffer"),
+        size: 4,
+        usage: wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    // Writing past the end panics in wgpu, but the device is fine
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        lost.catch(|| queue.write_buffer(&buffer, 4, &[0; 4]))
+    }));
+    assert!(result.is_err());
+    assert!(!lost.is_lost());
+}
@@ -0,0 +1,622 @@
+//! Golden-image tests: render fixed frames headlessly and compare them against
+//! the reference PNGs in `tests/golden/`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to (re)write the references after an intended change.
+//! On a mismatch the actual frame and a diff image are written to
+//! `target/tmp/golden/` so they can be inspected.
+
+use std::path::{Path, PathBuf};
+
+use advanced_wgpu::hdr::{TonemapSettings, Tonemapping};
+use advanced_wgpu::post::PostSettings;
+use advanced_wgpu::scene::{SceneGraph, Transform};
+use advanced_wgpu::{InitError, Projection, State};
+use cgmath::prelude::*;
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 128;
+
+/// Largest allowed difference of a single channel before a pixel counts as different.
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Fraction of pixels allowed to exceed `CHANNEL_TOLERANCE` (rasterizer edge differences).
+const MAX_DIFFERENT_PIXELS: f64 = 0.005;
+
+fn headless_state() -> Option<State> {
+    headless_state_with_format(State::HEADLESS_FORMAT)
+}
+
+fn headless_state_with_format(format: wgpu::TextureFormat) -> Option<State> {
+    let _ = env_logger::builder().is_test(true).try_init();
+    match pollster::block_on(State::new_headless_with_format(WIDTH, HEIGHT, format)) {
+        Ok(state) => Some(state),
+        // Only a missing GPU skips the test, broken shaders or textures have to fail it
+        Err(err @ (InitError::NoAdapter { .. } | InitError::RequestDevice { .. })) => {
+            eprintln!("Skipping golden-image test: {err:#}");
+            None
+        }
+        Err(err) => panic!(
+            "Couldn't set up the headless state: {:?}",
+            anyhow::Error::from(err)
+        ),
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{name}.png"))
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+fn assert_matches_golden(name: &str, actual: &image::RgbaImage) {
+    let path = golden_path(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save(&path).unwrap();
+        eprintln!("Wrote golden image {}", path.display());
+        return;
+    }
+    assert!(
+        path.exists(),
+        "{name}: missing golden image {}, run with UPDATE_GOLDEN=1 to create it",
+        path.display()
+    );
+
+    let expected = image::open(&path).unwrap().to_rgba8();
+    assert_eq!(
+        expected.dimensions(),
+        actual.dimensions(),
+        "{name}: golden image has a different size"
+    );
+
+    let mut diff = image::RgbaImage::new(actual.width(), actual.height());
+    let mut different_pixels = 0;
+    for (x, y, expected_pixel) in expected.enumerate_pixels() {
+        let actual_pixel = actual.get_pixel(x, y);
+        let max_delta = expected_pixel
+            .0
+            .iter()
+            .zip(actual_pixel.0.iter())
+            .map(|(e, a)| e.abs_diff(*a))
+            .max()
+            .unwrap();
+        if max_delta > CHANNEL_TOLERANCE {
+            different_pixels += 1;
+            diff.put_pixel(x, y, image::Rgba([255, 0, 0, 255]));
+        } else {
+            let grey = expected_pixel.0[..3].iter().map(|c| *c as u32).sum::<u32>() / 3 / 4;
+            diff.put_pixel(x, y, image::Rgba([grey as u8, grey as u8, grey as u8, 255]));
+        }
+    }
+
+    let total_pixels = (actual.width() * actual.height()) as f64;
+    if different_pixels as f64 / total_pixels > MAX_DIFFERENT_PIXELS {
+        let dir = output_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        let actual_path = dir.join(format!("{name}-actual.png"));
+        let diff_path = dir.join(format!("{name}-diff.png"));
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+        panic!(
+            "{name}: {different_pixels} of {total_pixels} pixels differ from {} (actual: {}, diff: {})",
+            path.display(),
+            actual_path.display(),
+            diff_path.display(),
+        );
+    }
+}
+
+#[test]
+fn instanced_pentagon() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("instanced_pentagon", &frame);
+}
+
+#[test]
+fn challenge_cube() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    state.show_challenge(true);
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("challenge_cube", &frame);
+}
+
+#[test]
+fn orthographic_resized() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    state.set_projection(Projection::Orthographic { half_height: 2. });
+    // Wider than tall, so the projection has to follow the new aspect ratio
+    state.resize(winit::dpi::PhysicalSize::new(WIDTH * 3 / 2, HEIGHT));
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("orthographic_resized", &frame);
+}
+
+#[test]
+fn msaa_resized() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    // Not every adapter can multisample the HDR format, so the golden is drawn without HDR
+    state.set_hdr(false);
+    if state.set_msaa_samples(4) != 4 {
+        eprintln!("Skipping MSAA test: 4x isn't supported");
+        return;
+    }
+    // The multisampled targets have to follow the size
+    state.resize(winit::dpi::PhysicalSize::new(WIDTH * 3 / 2, HEIGHT));
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("msaa_4x", &frame);
+
+    assert_eq!(state.set_msaa_samples(1), 1);
+    state.set_hdr(true);
+    state.resize(winit::dpi::PhysicalSize::new(WIDTH, HEIGHT));
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("instanced_pentagon", &frame);
+}
+
+#[test]
+fn tonemapped_bright_light() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    if !state.set_hdr(true) {
+        eprintln!("Skipping tonemapping test: no HDR target");
+        return;
+    }
+    // Far past 1.0, which would clip without tonemapping
+    state.set_light([2.0, 2.0, -1.0], [1.0, 0.8, 0.6], 4.0);
+    state.set_tonemap_settings(TonemapSettings {
+        tonemapping: Tonemapping::Reinhard,
+        exposure: 1.5,
+        gamma: 2.2,
+    });
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("tonemapped_bright_light", &frame);
+}
+
+#[test]
+fn bloom_bright_light() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    if !state.set_hdr(true) {
+        eprintln!("Skipping bloom test: no HDR target");
+        return;
+    }
+    state.set_light([2.0, 2.0, -1.0], [1.0, 0.8, 0.6], 4.0);
+    let mut settings = PostSettings::default();
+    settings.bloom.enabled = true;
+    settings.bloom.intensity = 0.5;
+    state.set_post_settings(settings);
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("bloom_bright_light", &frame);
+
+    // The bloom levels are reallocated, and dropped while bloom is off
+    state.resize(winit::dpi::PhysicalSize::new(WIDTH * 3 / 2, HEIGHT));
+    state.set_post_settings(PostSettings::default());
+    state.set_post_settings(settings);
+    state.resize(winit::dpi::PhysicalSize::new(WIDTH, HEIGHT));
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("bloom_bright_light", &frame);
+}
+
+#[test]
+fn post_chain_resized() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    if !state.set_hdr(true) {
+        eprintln!("Skipping post-processing test: no HDR target");
+        return;
+    }
+    let mut settings = PostSettings::default();
+    settings.color_grading.enabled = true;
+    settings.vignette.enabled = true;
+    settings.vignette.intensity = 0.8;
+    settings.fxaa.enabled = true;
+    state.set_post_settings(settings);
+    // The intermediate targets have to follow the size
+    state.resize(winit::dpi::PhysicalSize::new(WIDTH * 3 / 2, HEIGHT));
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("post_chain", &frame);
+
+    // Loading the built-in LUT from its file changes nothing
+    let luts = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("res")
+        .join("luts");
+    state.load_color_lut(luts.join("warm.png")).unwrap();
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("post_chain", &frame);
+
+    // Not laid out as a LUT
+    let not_a_lut = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("res")
+        .join("bumps.png");
+    assert!(state.load_color_lut(not_a_lut).is_err());
+
+    state.set_post_settings(PostSettings::default());
+    state.resize(winit::dpi::PhysicalSize::new(WIDTH, HEIGHT));
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("instanced_pentagon", &frame);
+}
+
+#[test]
+fn unorm_target_matches_srgb() {
+    let Some(mut state) = headless_state_with_format(wgpu::TextureFormat::Rgba8Unorm) else {
+        return;
+    };
+    if !state.set_hdr(true) {
+        eprintln!("Skipping non-sRGB test: no HDR target");
+        return;
+    }
+    // The tonemap and post-processing passes encode sRGB themselves for this format
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("instanced_pentagon", &frame);
+
+    let mut settings = PostSettings::default();
+    settings.color_grading.enabled = true;
+    settings.vignette.enabled = true;
+    settings.vignette.intensity = 0.8;
+    settings.fxaa.enabled = true;
+    state.set_post_settings(settings);
+    state.resize(winit::dpi::PhysicalSize::new(WIDTH * 3 / 2, HEIGHT));
+    let frame = state.capture_frame().unwrap();
+    // Its own golden: filtering an sRGB texture blends linear colors, so FXAA's edges differ
+    assert_matches_golden("post_chain_unorm", &frame);
+}
+
+#[test]
+fn frustum_culling_keeps_frame() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    let frame = state.capture_frame().unwrap();
+    let stats = state.culling_stats();
+    assert!(stats.culled > 0, "nothing culled: {:?}", stats);
+    assert!(stats.visible > 0, "everything culled: {:?}", stats);
+    // Culling only skips instances that wouldn't have been drawn anyway
+    assert_matches_golden("instanced_pentagon", &frame);
+
+    state.set_frustum_culling(false);
+    let unculled = state.capture_frame().unwrap();
+    assert_eq!(state.culling_stats().culled, 0);
+    assert_matches_golden("instanced_pentagon", &unculled);
+}
+
+#[test]
+fn gpu_instances_match_cpu() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    if !state.set_gpu_instances(true) {
+        eprintln!("Skipping GPU instance test: no compute shaders");
+        return;
+    }
+    let gpu = state.capture_frame().unwrap();
+    let gpu_stats = state.culling_stats();
+    assert_matches_golden("instanced_pentagon", &gpu);
+
+    state.set_gpu_instances(false);
+    let cpu = state.capture_frame().unwrap();
+    assert_matches_golden("instanced_pentagon", &cpu);
+    assert_eq!(gpu_stats, state.culling_stats());
+}
+
+#[test]
+fn scene_graph_hierarchy() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    let mut graph = SceneGraph::new();
+    let root = graph.add_node(
+        None,
+        Transform {
+            rotation: cgmath::Quaternion::from_angle_z(cgmath::Deg(30.)),
+            ..Default::default()
+        },
+        Some(0),
+    );
+    // Squashed, so the instance's normals have to account for the scale
+    let child = graph.add_node(
+        Some(root),
+        Transform {
+            translation: cgmath::Vector3::new(0.5, 0., 0.3),
+            scale: cgmath::Vector3::new(0.5, 1., 1.),
+            ..Default::default()
+        },
+        Some(0),
+    );
+    graph.add_node(
+        Some(child),
+        Transform::from_translation(cgmath::Vector3::new(0., -0.6, 0.3)),
+        Some(0),
+    );
+    state.set_scene_graph(graph);
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("scene_graph_hierarchy", &frame);
+
+    // Both instance paths have to apply the scale the same way
+    state.set_gpu_instances(false);
+    let cpu = state.capture_frame().unwrap();
+    assert_matches_golden("scene_graph_hierarchy", &cpu);
+}
+
+#[test]
+fn scene_graph_zero_scale() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    let rotation = cgmath::Quaternion::from_angle_z(cgmath::Deg(30.));
+    let mut graph = SceneGraph::new();
+    graph.add_node(
+        None,
+        Transform {
+            rotation,
+            ..Default::default()
+        },
+        Some(0),
+    );
+    state.set_scene_graph(graph);
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("scene_graph_zero_scale", &frame);
+
+    // The pentagons are flat, so flattening them along z keeps their rotation and lighting,
+    // and a node scaled to nothing hides its whole subtree
+    let mut graph = SceneGraph::new();
+    let root = graph.add_node(
+        None,
+        Transform {
+            rotation,
+            scale: cgmath::Vector3::new(1., 1., 0.),
+            ..Default::default()
+        },
+        Some(0),
+    );
+    let hidden = graph.add_node(
+        Some(root),
+        Transform {
+            translation: cgmath::Vector3::new(0.5, 0., 0.3),
+            scale: cgmath::Vector3::new(0., 0., 0.),
+            ..Default::default()
+        },
+        Some(0),
+    );
+    graph.add_node(
+        Some(hidden),
+        Transform::from_translation(cgmath::Vector3::new(0., -0.6, 0.3)),
+        Some(0),
+    );
+    state.set_scene_graph(graph);
+    let flattened = state.capture_frame().unwrap();
+    assert_matches_golden("scene_graph_zero_scale", &flattened);
+
+    state.set_gpu_instances(false);
+    let cpu = state.capture_frame().unwrap();
+    assert_matches_golden("scene_graph_zero_scale", &cpu);
+}
+
+#[test]
+fn obj_cube() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    state
+        .load_model(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("res")
+                .join("cube.obj"),
+        )
+        .unwrap();
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("obj_cube", &frame);
+}
+
+#[test]
+fn obj_cube_normal_map() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    state
+        .load_model(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("res")
+                .join("cube_bumpy.obj"),
+        )
+        .unwrap();
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("obj_cube_normal_map", &frame);
+}
+
+fn gltf_path(file: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("res")
+        .join("gltf")
+        .join(file)
+}
+
+#[test]
+fn gltf_embedded_buffer() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    state.load_gltf(gltf_path("cube_embedded.gltf")).unwrap();
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("gltf_cube", &frame);
+}
+
+#[test]
+fn gltf_binary() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    state.load_gltf(gltf_path("cube.glb")).unwrap();
+    let frame = state.capture_frame().unwrap();
+    // Same scene as `cube_embedded.gltf`, only packed differently
+    assert_matches_golden("gltf_cube", &frame);
+}
+
+#[test]
+fn gltf_sibling_files() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    state.load_gltf(gltf_path("cube.gltf")).unwrap();
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("gltf_cube_textured", &frame);
+}
+
+#[test]
+fn gltf_tinted_texture() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    // `cube.gltf` with a base color factor, which multiplies the texture
+    state.load_gltf(gltf_path("cube_tinted.gltf")).unwrap();
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("gltf_cube_tinted", &frame);
+}
+
+#[test]
+fn gltf_missing_file() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    assert!(state.load_gltf(gltf_path("missing.gltf")).is_err());
+}
+
+#[test]
+fn scene_file() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    let scenes = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("res")
+        .join("scenes");
+    state.load_scene(scenes.join("demo.ron")).unwrap();
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("scene_file", &frame);
+
+    // The JSON version describes the same scene
+    state.load_scene(scenes.join("demo.json")).unwrap();
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("scene_file", &frame);
+}
+
+#[test]
+fn device_recovery_keeps_scene() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    let scenes = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("res")
+        .join("scenes");
+    state.load_scene(scenes.join("demo.ron")).unwrap();
+    // Changes made after loading, which reloading the scene file would undo
+    state.set_projection(Projection::Orthographic { half_height: 2.5 });
+    state.set_light([-2.0, 2.0, 1.0], [1.0, 0.9, 0.8], 1.0);
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("device_recovery", &frame);
+
+    pollster::block_on(state.recover_device()).unwrap();
+    assert!(!state.is_device_lost());
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("device_recovery", &frame);
+}
+
+fn skybox_face_paths() -> [PathBuf; 6] {
+    let faces = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("res")
+        .join("skybox")
+        .join("faces");
+    ["px", "nx", "py", "ny", "pz", "nz"].map(|face| faces.join(format!("{face}.png")))
+}
+
+#[test]
+fn skybox_equirectangular() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    if !state.set_hdr(true) {
+        eprintln!("Skipping HDR skybox test: no HDR target");
+        return;
+    }
+    // High above the instances, looking up at the sun
+    state.set_camera([0.0, 20.0, 0.0], [0.4, 20.6, -0.7]);
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("skybox_equirectangular", &frame);
+
+    // Only the camera's rotation moves the sky
+    state.set_camera([5.0, 30.0, -5.0], [5.4, 30.6, -5.7]);
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("skybox_equirectangular", &frame);
+}
+
+#[test]
+fn skybox_equirectangular_ldr() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    // Drawn straight into the surface, which clamps the sky like the 8-bit cubemap adapters
+    // without HDR support convert it into
+    state.set_hdr(false);
+    state.set_camera([0.0, 20.0, 0.0], [0.4, 20.6, -0.7]);
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("skybox_equirectangular_ldr", &frame);
+}
+
+#[test]
+fn skybox_faces() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    state.load_skybox_faces(skybox_face_paths()).unwrap();
+    // Into the corner of the +X, +Y and -Z faces, with each face's marker at a different spot
+    state.set_camera([0.0, 20.0, 0.0], [1.0, 21.0, -1.0]);
+    // Drawn straight into the surface format, with the pipeline retargeted to it
+    state.set_hdr(false);
+    let frame = state.capture_frame().unwrap();
+    assert_matches_golden("skybox_faces", &frame);
+
+    // The clear color shows instead
+    state.set_skybox_visible(false);
+    let hidden = state.capture_frame().unwrap();
+    assert_ne!(hidden.get_pixel(64, 64), frame.get_pixel(64, 64));
+}
+
+#[test]
+fn skybox_faces_must_match() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    let mut paths = skybox_face_paths();
+    paths[3] = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("res")
+        .join("bumps.png");
+    let err = state.load_skybox_faces(paths).unwrap_err();
+    assert!(format!("{err:#}").contains("face 3"), "{err:#}");
+}
+
+#[test]
+fn skybox_radiance_too_large() {
+    let Some(mut state) = headless_state() else {
+        return;
+    };
+    // Only a header, which would need gigabytes if it were decoded
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("too_large.hdr");
+    std::fs::write(
+        &path,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 100000 +X 200000\n",
+    )
+    .unwrap();
+    let err = state.load_skybox_equirectangular(&path).unwrap_err();
+    assert!(format!("{err:#}").contains("doesn't fit"), "{err:#}");
+}
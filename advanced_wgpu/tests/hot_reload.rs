@@ -0,0 +1,141 @@
+//! Shader hot-reload: naga validation of the shipped shaders, and pipeline rebuilds that
+//! keep the last good pipeline when a new shader doesn't compile.
+
+use std::path::Path;
+
+use advanced_wgpu::pipeline::{PipelineCache, PipelineDesc};
+use advanced_wgpu::{hot_reload, post};
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+const RED: &str = "
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(f32(i) - 1.0, 0.0, 0.0, 1.0);
+}
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+}
+";
+
+fn device() -> Option<wgpu::Device> {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::default(),
+        compatible_surface: None,
+        force_fallback_adapter: false,
+    }));
+    let Some(adapter) = adapter else {
+        eprintln!("Skipping hot-reload test: no adapter");
+        return None;
+    };
+    let (device, _queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::downlevel_webgl2_defaults(),
+        },
+        None,
+    ))
+    .ok()?;
+    Some(device)
+}
+
+#[test]
+fn shipped_shaders_validate() {
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    for name in [
+        "shader.wgsl",
+        "challenge.wgsl",
+        "light.wgsl",
+        "shadow.wgsl",
+        "instance_compute.wgsl",
+        "skybox.wgsl",
+        "fullscreen.wgsl",
+    ] {
+        let path = src.join(name);
+        let source = std::fs::read_to_string(&path).unwrap();
+        if let Err(diagnostic) = hot_reload::validate(&path, &source) {
+            panic!("{name} doesn't validate:\n{diagnostic}");
+        }
+    }
+}
+
+#[test]
+fn fullscreen_shaders_validate_with_prelude() {
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    for name in [
+        "tonemap.wgsl",
+        "bloom_downsample.wgsl",
+        "bloom_upsample.wgsl",
+        "color_grading.wgsl",
+        "vignette.wgsl",
+        "fxaa.wgsl",
+        "equirect_to_cube.wgsl",
+    ] {
+        let path = src.join(name);
+        // Loaded the way the pipelines are built from it
+        let source = post::fullscreen_shader(&std::fs::read_to_string(&path).unwrap());
+        if let Err(diagnostic) = hot_reload::validate(&path, &source) {
+            panic!("{name} doesn't validate:\n{diagnostic}");
+        }
+    }
+}
+
+#[test]
+fn invalid_shader_reports_diagnostic() {
+    let source = RED.replace("return vec4<f32>(1.0", "return vec3<f32>(1.0");
+    let diagnostic = hot_reload::validate(Path::new("red.wgsl"), &source).unwrap_err();
+    assert!(!diagnostic.is_empty());
+}
+
+#[test]
+fn replace_shader_keeps_last_good_pipeline() {
+    let Some(device) = device() else {
+        return;
+    };
+    let mut pipelines = PipelineCache::new();
+    pipelines.add_layout(
+        "empty",
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        }),
+    );
+    let desc = PipelineDesc::new("Red", RED, "empty", FORMAT);
+    let id = pipelines.get_or_create(&device, &desc);
+    assert_eq!(pipelines.get_or_create(&device, &desc), id);
+
+    // Valid WGSL, but it needs a bind group the layout doesn't have
+    let needs_binding = RED
+        .replace(
+            "@fragment",
+            "@group(0) @binding(0) var<uniform> color: vec4<f32>;\n@fragment",
+        )
+        .replace("return vec4<f32>(1.0, 0.0, 0.0, 1.0);", "return color;");
+    assert!(pipelines
+        .replace_shader(&device, RED, needs_binding.into())
+        .is_err());
+    assert_eq!(pipelines.len(), 1);
+
+    let green = RED.replace(
+        "vec4<f32>(1.0, 0.0, 0.0, 1.0)",
+        "vec4<f32>(0.0, 1.0, 0.0, 1.0)",
+    );
+    assert_eq!(
+        pipelines
+            .replace_shader(&device, RED, green.clone().into())
+            .unwrap(),
+        1
+    );
+    assert_eq!(pipelines.len(), 1);
+    // The rebuilt pipeline keeps its id and is found under the new source
+    let green_desc = PipelineDesc {
+        shader: green.into(),
+        ..desc
+    };
+    assert_eq!(pipelines.get_or_create(&device, &green_desc), id);
+}
@@ -0,0 +1,36 @@
+//! Messages of the setup errors returned instead of panics.
+
+use advanced_wgpu::InitError;
+
+#[test]
+fn no_adapter_names_the_backends() {
+    let error = InitError::NoAdapter {
+        backends: wgpu::Backends::VULKAN,
+        force_fallback_adapter: true,
+    };
+    assert_eq!(
+        error.to_string(),
+        "No fallback adapter available for backends VULKAN"
+    );
+
+    let error = InitError::NoAdapter {
+        backends: wgpu::Backends::GL,
+        force_fallback_adapter: false,
+    };
+    assert_eq!(error.to_string(), "No adapter available for backends GL");
+}
+
+#[test]
+fn request_device_keeps_the_cause() {
+    let error = anyhow::Error::from(InitError::RequestDevice {
+        adapter: "llvmpipe".to_string(),
+        source: wgpu::RequestDeviceError,
+    });
+    assert_eq!(
+        format!("{:#}", error),
+        format!(
+            "Failed to get a device from llvmpipe: {}",
+            wgpu::RequestDeviceError
+        )
+    );
+}
@@ -0,0 +1,205 @@
+//! Render graph: pass ordering from declared reads and writes, transient texture lifetimes
+//! and aliasing, and reallocation on resize, checked on a headless device.
+
+use std::cell::RefCell;
+
+use advanced_wgpu::render_graph::{
+    GraphError, PassDesc, RenderGraph, ResourceId, TextureDesc, TextureSize,
+};
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+fn device() -> Option<wgpu::Device> {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::default(),
+        compatible_surface: None,
+        force_fallback_adapter: false,
+    }));
+    let Some(adapter) = adapter else {
+        eprintln!("Skipping render graph test: no adapter");
+        return None;
+    };
+    let (device, _queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::downlevel_webgl2_defaults(),
+        },
+        None,
+    ))
+    .ok()?;
+    Some(device)
+}
+
+/// The names of the passes in the order they ran.
+type Log = RefCell<Vec<&'static str>>;
+
+fn add_logged_pass(graph: &mut RenderGraph<Log>, desc: PassDesc) {
+    let name = desc.name;
+    graph.add_pass(desc, move |log: &Log, _| log.borrow_mut().push(name));
+}
+
+fn color_texture(graph: &mut RenderGraph<Log>, label: &'static str) -> ResourceId {
+    graph.add_texture(TextureDesc::new(
+        label,
+        FORMAT,
+        wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    ))
+}
+
+fn run(device: &wgpu::Device, graph: &RenderGraph<Log>) -> Vec<&'static str> {
+    let output = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("output"),
+        size: wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    });
+    let output = output.create_view(&wgpu::TextureViewDescriptor::default());
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    let log = Log::default();
+    graph.execute(&mut encoder, &output, &log);
+    log.into_inner()
+}
+
+fn size(graph: &RenderGraph<Log>, texture: ResourceId) -> (u32, u32) {
+    let size = graph.texture_size(texture).unwrap();
+    (size.width, size.height)
+}
+
+#[test]
+fn passes_run_after_what_they_read() {
+    let Some(device) = device() else {
+        return;
+    };
+    let mut graph = RenderGraph::new();
+    let frame = graph.import();
+    let shadow_map = graph.import();
+    let scene = color_texture(&mut graph, "scene");
+    // Added in the wrong order on purpose
+    add_logged_pass(
+        &mut graph,
+        PassDesc::new("tonemap").read(scene).write(frame),
+    );
+    add_logged_pass(
+        &mut graph,
+        PassDesc::new("scene").read(shadow_map).write(scene),
+    );
+    // Adds onto the scene, so it runs after the scene pass that was added before it
+    add_logged_pass(&mut graph, PassDesc::new("bloom").read(scene).write(scene));
+    add_logged_pass(&mut graph, PassDesc::new("shadow").write(shadow_map));
+    add_logged_pass(&mut graph, PassDesc::new("ui"));
+    graph.compile(&device, 64, 64).unwrap();
+
+    let expected = ["shadow", "scene", "bloom", "tonemap", "ui"];
+    assert_eq!(graph.execution_order(), expected);
+    assert_eq!(run(&device, &graph), expected);
+}
+
+#[test]
+fn dependency_cycle_is_an_error() {
+    let Some(device) = device() else {
+        return;
+    };
+    let mut graph = RenderGraph::new();
+    let a = graph.import();
+    let b = graph.import();
+    add_logged_pass(&mut graph, PassDesc::new("first").read(a).write(b));
+    add_logged_pass(&mut graph, PassDesc::new("second").read(b).write(a));
+    add_logged_pass(&mut graph, PassDesc::new("unrelated"));
+    match graph.compile(&device, 64, 64) {
+        Err(GraphError::Cycle(passes)) => assert_eq!(passes, ["first", "second"]),
+        other => panic!("expected a cycle, got {:?}", other),
+    }
+}
+
+#[test]
+fn transient_textures_alias_when_lifetimes_dont_overlap() {
+    let Some(device) = device() else {
+        return;
+    };
+    let mut graph = RenderGraph::new();
+    let frame = graph.import();
+    let first = color_texture(&mut graph, "first");
+    let second = color_texture(&mut graph, "second");
+    let third = color_texture(&mut graph, "third");
+    let depth = graph.add_texture(TextureDesc::new(
+        "depth",
+        wgpu::TextureFormat::Depth32Float,
+        wgpu::TextureUsages::RENDER_ATTACHMENT,
+    ));
+    let unused = color_texture(&mut graph, "unused");
+    add_logged_pass(&mut graph, PassDesc::new("a").write(first).write(depth));
+    add_logged_pass(&mut graph, PassDesc::new("b").read(first).write(second));
+    add_logged_pass(&mut graph, PassDesc::new("c").read(second).write(third));
+    add_logged_pass(&mut graph, PassDesc::new("d").read(third).write(frame));
+    graph.compile(&device, 64, 64).unwrap();
+
+    assert_eq!(graph.lifetime(first), Some(0..2));
+    assert_eq!(graph.lifetime(second), Some(1..3));
+    assert_eq!(graph.lifetime(third), Some(2..4));
+    assert_eq!(graph.lifetime(depth), Some(0..1));
+    assert_eq!(graph.lifetime(frame), Some(3..4));
+    assert_eq!(graph.lifetime(unused), None);
+
+    // `first` is done before `third` is drawn, `second` overlaps both
+    assert!(graph.aliases(first, third));
+    assert!(!graph.aliases(first, second));
+    assert!(!graph.aliases(second, third));
+    // Free by then, but a different format
+    assert!(!graph.aliases(depth, second));
+    assert_eq!(graph.texture_size(unused), None);
+    assert_eq!(graph.texture_count(), 3);
+    assert_eq!(run(&device, &graph), ["a", "b", "c", "d"]);
+}
+
+#[test]
+fn resize_reallocates_surface_sized_textures() {
+    let Some(device) = device() else {
+        return;
+    };
+    let mut graph = RenderGraph::new();
+    let frame = graph.import();
+    let scene = color_texture(&mut graph, "scene");
+    let bloom = graph.add_texture(
+        TextureDesc::new("bloom", FORMAT, wgpu::TextureUsages::RENDER_ATTACHMENT).size(
+            TextureSize::Fixed {
+                width: 16,
+                height: 8,
+            },
+        ),
+    );
+    let half = graph.add_texture(
+        TextureDesc::new("half", FORMAT, wgpu::TextureUsages::RENDER_ATTACHMENT)
+            .size(TextureSize::Downscaled(1)),
+    );
+    add_logged_pass(
+        &mut graph,
+        PassDesc::new("scene").write(scene).write(bloom).write(half),
+    );
+    add_logged_pass(
+        &mut graph,
+        PassDesc::new("tonemap").read(scene).write(frame),
+    );
+    graph.compile(&device, 64, 32).unwrap();
+    assert_eq!(size(&graph, scene), (64, 32));
+    assert_eq!(size(&graph, bloom), (16, 8));
+    assert_eq!(size(&graph, half), (32, 16));
+    assert!(graph.texture(scene).is_some());
+    assert!(graph.texture(frame).is_none());
+
+    graph.resize(&device, 100, 50);
+    assert_eq!(size(&graph, scene), (100, 50));
+    assert_eq!(size(&graph, bloom), (16, 8));
+    assert_eq!(size(&graph, half), (50, 25));
+    assert_eq!(run(&device, &graph), ["scene", "tonemap"]);
+}
@@ -0,0 +1,66 @@
+//! Scene file parsing and the validation errors for bad entries.
+
+use advanced_wgpu::scene_file::{MeshSource, SceneDesc};
+
+fn error(source: &str) -> String {
+    format!("{:#}", SceneDesc::from_ron(source).unwrap_err())
+}
+
+#[test]
+fn example_scenes_agree() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("res")
+        .join("scenes");
+    let ron = SceneDesc::load(dir.join("demo.ron")).unwrap();
+    let json = SceneDesc::load(dir.join("demo.json")).unwrap();
+    assert_eq!(ron, json);
+    assert_eq!(ron.meshes[2].source, MeshSource::Obj("../cube.obj".into()));
+    assert_eq!(ron.instances[0].scale, [1., 1., 1.]);
+    assert_eq!(ron.camera.znear, 0.1);
+}
+
+#[test]
+fn errors_name_the_entry() {
+    let cases = [
+        (
+            r#"(meshes: [(name: "a", source: Cube, texture: "missing")])"#,
+            r#"mesh "a": unknown texture "missing""#,
+        ),
+        (
+            r#"(meshes: [(name: "a", source: Cube), (name: "a", source: Pentagon)])"#,
+            r#"mesh "a": defined more than once"#,
+        ),
+        (
+            r#"(meshes: [(name: "a", source: Cube)], instances: [
+                (mesh: "a", position: (0, 0, 0)),
+                (mesh: "b", position: (0, 0, 0)),
+            ])"#,
+            r#"instance 1: unknown mesh "b""#,
+        ),
+        (
+            r#"(meshes: [(name: "a", source: Cube)], instances: [
+                (mesh: "a", position: (0, 0, 0), scale: (1, 0, 1)),
+            ])"#,
+            "instance 0 (a): scale can't be zero",
+        ),
+        (
+            r#"(meshes: [], camera: (znear: 0))"#,
+            "camera: needs 0 < znear < zfar, got znear 0 and zfar 100",
+        ),
+        (
+            r#"(meshes: [], camera: (orthographic_half_height: -1))"#,
+            "camera: orthographic_half_height must be positive, got -1",
+        ),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(error(source), expected);
+    }
+}
+
+#[test]
+fn parse_errors_have_a_position() {
+    let message = error(r#"(meshes: [(name: "a", source: Sphere)])"#);
+    assert!(message.starts_with("1:"), "{}", message);
+    let message = error(r#"(meshes: [], colour: (1, 1, 1))"#);
+    assert!(message.contains("colour"), "{}", message);
+}
@@ -0,0 +1,67 @@
+//! Scene graph transform propagation and dirty tracking.
+
+use advanced_wgpu::scene::{SceneGraph, Transform};
+use cgmath::prelude::*;
+
+fn assert_near(actual: cgmath::Vector3<f32>, expected: cgmath::Vector3<f32>) {
+    assert!(
+        (actual - expected).magnitude() < 1e-5,
+        "expected {:?}, got {:?}",
+        expected,
+        actual
+    );
+}
+
+#[test]
+fn world_matrix_combines_ancestors() {
+    let mut graph = SceneGraph::new();
+    let root = graph.add_node(
+        None,
+        Transform {
+            translation: cgmath::Vector3::new(1., 0., 0.),
+            rotation: cgmath::Quaternion::from_angle_y(cgmath::Deg(90.)),
+            ..Default::default()
+        },
+        None,
+    );
+    let child = graph.add_node(
+        Some(root),
+        Transform {
+            translation: cgmath::Vector3::new(0., 0., 1.),
+            scale: cgmath::Vector3::new(2., 2., 2.),
+            ..Default::default()
+        },
+        Some(0),
+    );
+    assert_eq!(graph.parent(child), Some(root));
+    assert_eq!(graph.children(root), &[child]);
+    graph.update_world_transforms();
+
+    // The child's offset is turned by the root's rotation, then moved by its translation
+    let world = graph.world_matrix(child);
+    assert_near(world.w.truncate(), cgmath::Vector3::new(2., 0., 0.));
+    assert_near(world.x.truncate(), cgmath::Vector3::new(0., 0., -2.));
+}
+
+#[test]
+fn only_dirty_subtrees_are_recomputed() {
+    let mut graph = SceneGraph::new();
+    let root = graph.add_node(None, Transform::default(), Some(0));
+    let left = graph.add_node(Some(root), Transform::default(), Some(0));
+    graph.add_node(Some(left), Transform::default(), Some(0));
+    let right = graph.add_node(Some(root), Transform::default(), Some(0));
+    graph.add_node(Some(right), Transform::default(), Some(0));
+
+    assert_eq!(graph.update_world_transforms(), 5);
+    assert_eq!(graph.update_world_transforms(), 0);
+
+    graph.set_local_transform(
+        left,
+        Transform::from_translation(cgmath::Vector3::new(0., 1., 0.)),
+    );
+    assert_eq!(graph.update_world_transforms(), 2);
+
+    graph.set_spin(root, Some((cgmath::Vector3::unit_y(), cgmath::Rad(1.))));
+    graph.animate(0.1);
+    assert_eq!(graph.update_world_transforms(), 5);
+}
@@ -6,6 +6,8 @@ use winit::{
 
 use wgpu::util::DeviceExt;
 
+pub use wgpu_pipeline as pipeline;
+
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
@@ -54,6 +56,43 @@ const VERTICES: &[Vertex] = &[
     },
 ];
 
+/// Samples per pixel when the adapter can multisample the surface format. wgpu 0.13 only
+/// renders with 1 or 4.
+const MSAA_SAMPLE_COUNT: u32 = 4;
+
+/// Whether the adapter can render `format` with [`MSAA_SAMPLE_COUNT`] samples and resolve it.
+fn supports_msaa(adapter: &wgpu::Adapter, format: wgpu::TextureFormat) -> bool {
+    adapter.get_texture_format_features(format).flags.contains(
+        wgpu::TextureFormatFeatureFlags::MULTISAMPLE
+            | wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE,
+    )
+}
+
+/// The multisampled color target resolved into the surface texture, if there's more than
+/// one sample.
+fn create_msaa_view(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    sample_count: u32,
+) -> Option<wgpu::TextureView> {
+    if sample_count == 1 {
+        return None;
+    }
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("MSAA Texture"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    });
+    Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+}
 
 struct State {
     surface: wgpu::Surface,
@@ -63,10 +102,16 @@ struct State {
     size: winit::dpi::PhysicalSize<u32>,
     clear_color: wgpu::Color,
 
-    render_pipeline: wgpu::RenderPipeline,
-    challenge_pipeline: wgpu::RenderPipeline,
+    pipelines: pipeline::PipelineCache,
+    render_pipeline: pipeline::PipelineId,
+    challenge_pipeline: pipeline::PipelineId,
     use_main_shader: bool,
 
+    /// [`MSAA_SAMPLE_COUNT`], or 1 if the adapter can't or MSAA was turned off with `M`.
+    sample_count: u32,
+    msaa_supported: bool,
+    msaa_view: Option<wgpu::TextureView>,
+
     vertex_buffer: wgpu::Buffer,
     num_vertices: u32,
 }
@@ -110,93 +155,41 @@ impl State {
             present_mode: wgpu::PresentMode::Fifo,
         };
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
-        let render_pipeline_layout =
+        let msaa_supported = supports_msaa(&adapter, config.format);
+        let sample_count = if msaa_supported { MSAA_SAMPLE_COUNT } else { 1 };
+        let msaa_view = create_msaa_view(&device, &config, sample_count);
+
+        let mut pipelines = pipeline::PipelineCache::new();
+        pipelines.add_layout(
+            "main",
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
                 bind_group_layouts: &[],
                 push_constant_ranges: &[],
-            });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[
-                    Vertex::desc(),
-                ],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
-
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("challenge.wgsl").into()),
-        });
-        let challenge_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[
-                    Vertex::desc(),
-                ],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
             }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+        );
+        let render_pipeline = pipelines.get_or_create(
+            &device,
+            &pipeline::PipelineDesc::new(
+                "Render Pipeline",
+                include_str!("shader.wgsl"),
+                "main",
+                config.format,
+            )
+            .vertex_layouts(&[Vertex::desc()])
+            .sample_count(sample_count),
+        );
+        let challenge_pipeline = pipelines.get_or_create(
+            &device,
+            &pipeline::PipelineDesc::new(
+                "Challenge Pipeline",
+                include_str!("challenge.wgsl"),
+                "main",
+                config.format,
+            )
+            .vertex_layouts(&[Vertex::desc()])
+            .sample_count(sample_count),
+        );
 
         let vertex_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
@@ -221,9 +214,13 @@ impl State {
             config,
             size,
             clear_color,
+            pipelines,
             render_pipeline,
             challenge_pipeline,
             use_main_shader,
+            sample_count,
+            msaa_supported,
+            msaa_view,
             vertex_buffer,
             num_vertices,
         }
@@ -234,6 +231,24 @@ impl State {
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
+            self.msaa_view = create_msaa_view(&self.device, &self.config, self.sample_count);
+        }
+    }
+    /// Switches between [`MSAA_SAMPLE_COUNT`] samples and none, if the adapter supports MSAA.
+    fn toggle_msaa(&mut self) {
+        if !self.msaa_supported {
+            log::warn!("MSAA isn't supported for {:?}", self.config.format);
+            return;
+        }
+        self.sample_count = if self.sample_count == 1 { MSAA_SAMPLE_COUNT } else { 1 };
+        self.msaa_view = create_msaa_view(&self.device, &self.config, self.sample_count);
+        for pipeline in [&mut self.render_pipeline, &mut self.challenge_pipeline] {
+            *pipeline = self.pipelines.retarget(
+                &self.device,
+                *pipeline,
+                self.config.format,
+                self.sample_count,
+            );
         }
     }
     fn input(&mut self, event: &WindowEvent) -> bool {
@@ -255,6 +270,15 @@ impl State {
                     },
                     ..
             } => self.use_main_shader = *state != ElementState::Pressed,
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::M),
+                        ..
+                    },
+                    ..
+            } => self.toggle_msaa(),
             _ => {}
         }
         false
@@ -274,9 +298,10 @@ impl State {
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
+                // With MSAA the samples are drawn into `msaa_view` and resolved to the surface
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
+                    view: self.msaa_view.as_ref().unwrap_or(&view),
+                    resolve_target: self.msaa_view.as_ref().map(|_| &view),
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(self.clear_color),
                         store: true,
@@ -285,10 +310,10 @@ impl State {
                 depth_stencil_attachment: None,
             });
             if self.use_main_shader {
-                render_pass.set_pipeline(&self.render_pipeline);
+                render_pass.set_pipeline(&self.pipelines[self.render_pipeline]);
             }
             else {
-                render_pass.set_pipeline(&self.challenge_pipeline);
+                render_pass.set_pipeline(&self.pipelines[self.challenge_pipeline]);
             }
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.draw(0..self.num_vertices, 0..1);
@@ -302,6 +327,8 @@ impl State {
 }
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
+// The event loop keeps the tutorial's nesting, so each step's changes stay easy to follow
+#[allow(clippy::collapsible_match)]
 pub async fn run() {
     cfg_if::cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
@@ -0,0 +1,281 @@
+//! Render pipeline descriptions and the cache that builds them, shared by `hello_triangle` and
+//! `advanced_wgpu`, which both re-export it as their `pipeline` module.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// The format of the depth textures that [`DepthState::default`] tests against.
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Depth testing for pipelines drawn with a depth attachment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DepthState {
+    pub format: wgpu::TextureFormat,
+    pub compare: wgpu::CompareFunction,
+    pub write_enabled: bool,
+}
+impl Default for DepthState {
+    fn default() -> Self {
+        Self {
+            format: DEPTH_FORMAT,
+            compare: wgpu::CompareFunction::Less,
+            write_enabled: true,
+        }
+    }
+}
+
+/// Describes a render pipeline whose shader has `vs_main` and `fs_main` entry points.
+/// It is also the key under which [`PipelineCache`] stores the pipeline built from it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineDesc {
+    pub label: &'static str,
+    /// WGSL source of the shader module.
+    pub shader: Cow<'static, str>,
+    /// Name of a layout added with [`PipelineCache::add_layout`].
+    pub layout: &'static str,
+    pub vertex_layouts: Vec<wgpu::VertexBufferLayout<'static>>,
+    pub color_format: wgpu::TextureFormat,
+    pub blend: Option<wgpu::BlendState>,
+    pub cull_mode: Option<wgpu::Face>,
+    /// Anything but `Fill` needs the matching device feature, e.g. `POLYGON_MODE_LINE`.
+    pub polygon_mode: wgpu::PolygonMode,
+    pub depth: Option<DepthState>,
+    pub sample_count: u32,
+}
+impl PipelineDesc {
+    /// An opaque, back-face culled pipeline without vertex buffers, depth testing or
+    /// multisampling. The other methods change one setting each.
+    pub fn new(
+        label: &'static str,
+        shader: impl Into<Cow<'static, str>>,
+        layout: &'static str,
+        color_format: wgpu::TextureFormat,
+    ) -> Self {
+        Self {
+            label,
+            shader: shader.into(),
+            layout,
+            vertex_layouts: Vec::new(),
+            color_format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            depth: None,
+            sample_count: 1,
+        }
+    }
+    pub fn vertex_layouts(mut self, vertex_layouts: &[wgpu::VertexBufferLayout<'static>]) -> Self {
+        self.vertex_layouts = vertex_layouts.to_vec();
+        self
+    }
+    pub fn blend(mut self, blend: Option<wgpu::BlendState>) -> Self {
+        self.blend = blend;
+        self
+    }
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+    pub fn polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+    pub fn depth(mut self, depth: DepthState) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+    fn build(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(self.label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &self.vertex_layouts,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: self.color_format,
+                    blend: self.blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: self.cull_mode,
+                polygon_mode: self.polygon_mode,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: self.depth.map(|depth| wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: depth.write_enabled,
+                depth_compare: depth.compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: self.sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+}
+
+/// Joins WGSL declarations shared between shaders and a shader that uses them.
+pub fn with_prelude(prelude: &str, source: &str) -> String {
+    format!("{}\n{}", prelude, source)
+}
+
+/// Handle to a pipeline in a [`PipelineCache`], cheap to store and to look up every frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineId(usize);
+
+/// Builds each distinct [`PipelineDesc`] once, sharing shader modules between pipelines
+/// with the same source.
+#[derive(Default)]
+pub struct PipelineCache {
+    layouts: HashMap<&'static str, wgpu::PipelineLayout>,
+    shaders: HashMap<Cow<'static, str>, wgpu::ShaderModule>,
+    ids: HashMap<PipelineDesc, PipelineId>,
+    pipelines: Vec<wgpu::RenderPipeline>,
+}
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Registers a pipeline layout for descriptions to refer to by `name`.
+    pub fn add_layout(&mut self, name: &'static str, layout: wgpu::PipelineLayout) {
+        self.layouts.insert(name, layout);
+    }
+    /// Returns the pipeline for `desc`, building it the first time it's asked for.
+    ///
+    /// # Panics
+    ///
+    /// If `desc.layout` hasn't been added with [`Self::add_layout`].
+    pub fn get_or_create(&mut self, device: &wgpu::Device, desc: &PipelineDesc) -> PipelineId {
+        if let Some(&id) = self.ids.get(desc) {
+            return id;
+        }
+
+        let layout = self
+            .layouts
+            .get(desc.layout)
+            .unwrap_or_else(|| panic!("Unknown pipeline layout {:?}", desc.layout));
+        let shader = self.shaders.entry(desc.shader.clone()).or_insert_with(|| {
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(desc.label),
+                source: wgpu::ShaderSource::Wgsl(desc.shader.clone()),
+            })
+        });
+        let id = PipelineId(self.pipelines.len());
+        self.pipelines.push(desc.build(device, layout, shader));
+        self.ids.insert(desc.clone(), id);
+        id
+    }
+    /// Returns the pipeline built like `id` but drawing into `color_format` with
+    /// `sample_count` samples per pixel, with the shader `id` has now (which may have been
+    /// replaced since).
+    ///
+    /// # Panics
+    ///
+    /// If `id` isn't from this cache.
+    pub fn retarget(
+        &mut self,
+        device: &wgpu::Device,
+        id: PipelineId,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> PipelineId {
+        let desc = self
+            .ids
+            .iter()
+            .find(|(_, &other)| other == id)
+            .map(|(desc, _)| desc.clone())
+            .unwrap_or_else(|| panic!("Unknown pipeline {:?}", id));
+        let desc = PipelineDesc {
+            color_format,
+            ..desc.sample_count(sample_count)
+        };
+        self.get_or_create(device, &desc)
+    }
+    /// Rebuilds every pipeline built from the `old` shader source with `new` instead, keeping
+    /// their ids. If the new shader or any of the pipelines fails validation, the error is
+    /// returned and the cache is left unchanged. Returns the number of pipelines rebuilt.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn replace_shader(
+        &mut self,
+        device: &wgpu::Device,
+        old: &str,
+        new: Cow<'static, str>,
+    ) -> Result<usize, wgpu::Error> {
+        let affected = self
+            .ids
+            .iter()
+            .filter(|(desc, _)| desc.shader == old)
+            .map(|(desc, &id)| (desc.clone(), id))
+            .collect::<Vec<_>>();
+        if affected.is_empty() {
+            return Ok(0);
+        }
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(affected[0].0.label),
+            source: wgpu::ShaderSource::Wgsl(new.clone()),
+        });
+        let rebuilt = affected
+            .iter()
+            .map(|(desc, _)| {
+                let desc = PipelineDesc {
+                    shader: new.clone(),
+                    ..desc.clone()
+                };
+                let pipeline = desc.build(device, &self.layouts[desc.layout], &shader);
+                (desc, pipeline)
+            })
+            .collect::<Vec<_>>();
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            return Err(error);
+        }
+
+        let count = affected.len();
+        for ((old_desc, id), (desc, pipeline)) in affected.into_iter().zip(rebuilt) {
+            self.ids.remove(&old_desc);
+            self.ids.insert(desc, id);
+            self.pipelines[id.0] = pipeline;
+        }
+        self.shaders.remove(old);
+        self.shaders.insert(new, shader);
+        Ok(count)
+    }
+    /// The number of pipelines built so far.
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+}
+impl std::ops::Index<PipelineId> for PipelineCache {
+    type Output = wgpu::RenderPipeline;
+
+    fn index(&self, id: PipelineId) -> &Self::Output {
+        &self.pipelines[id.0]
+    }
+}